@@ -0,0 +1,158 @@
+//! decode throughput of `EslCodec` on captured traffic
+//!
+//! `ESL_CAPTURE=esl.raw cargo bench --bench codec`
+//!
+//! `esl.raw` is what freeswitch wrote to an inbound socket, byte for byte,
+//! e.g. the server side of `tcpflow -r esl.pcap port 8021` for a capture of an
+//! `event json ALL` subscription taken while a load test ran. it is replayed
+//! in TCP sized chunks.
+//!
+//! without `ESL_CAPTURE` a generated stream stands in: a call burst of full
+//! CHANNEL_* events with ~80 channel variables, command replies and api
+//! responses.
+//!
+//! captured traffic has not been measured yet, every figure so far is from
+//! the generated stream.
+
+use bytes::BytesMut;
+use esl_rs::{
+    codec::EslCodec,
+    event::{Event, EventData},
+};
+use std::time::Instant;
+
+const CALLS: usize = 2_000;
+
+fn channel_event(name: &str, uuid: &str, seq: usize) -> String {
+    let mut body = serde_json::Map::new();
+    let mut put = |k: &str, v: String| {
+        body.insert(k.to_string(), serde_json::Value::String(v));
+    };
+    put("Event-Name", name.to_string());
+    put(
+        "Core-UUID",
+        "6d2375b0-5183-11e1-b24c-f527b57af4b2".to_string(),
+    );
+    put("FreeSWITCH-Hostname", "fs01.example.net".to_string());
+    put("Event-Date-Local", "2023-11-14 22:13:20".to_string());
+    put("Event-Date-Timestamp", "1700000000000000".to_string());
+    put("Event-Calling-File", "switch_channel.c".to_string());
+    put("Event-Sequence", seq.to_string());
+    put("Unique-ID", uuid.to_string());
+    put("Channel-State", "CS_EXECUTE".to_string());
+    put("Channel-Call-State", "ACTIVE".to_string());
+    put(
+        "Channel-Name",
+        format!("sofia/internal/1000@{}", "10.0.0.1"),
+    );
+    put("Caller-Caller-ID-Number", "1000".to_string());
+    put("Caller-Destination-Number", "5551234".to_string());
+    put(
+        "Caller-Channel-Created-Time",
+        "1700000000000000".to_string(),
+    );
+    put(
+        "Caller-Channel-Answered-Time",
+        "1700000003000000".to_string(),
+    );
+    for i in 0..80 {
+        put(
+            &format!("variable_var_{}", i),
+            format!("value-{}-{}", i, uuid),
+        );
+    }
+    let body = serde_json::Value::Object(body).to_string();
+    format!(
+        "Content-Length: {}\nContent-Type: text/event-json\n\n{}",
+        body.len(),
+        body
+    )
+}
+
+fn synthetic() -> Vec<u8> {
+    let mut out = String::new();
+    let mut seq = 0;
+    for call in 0..CALLS {
+        let uuid = format!("{:08x}-1c2d-4e5f-8a9b-{:012x}", call, call);
+        for name in [
+            "CHANNEL_CREATE",
+            "CHANNEL_PROGRESS",
+            "CHANNEL_ANSWER",
+            "CHANNEL_BRIDGE",
+            "CHANNEL_HANGUP",
+            "CHANNEL_HANGUP_COMPLETE",
+            "CHANNEL_DESTROY",
+        ] {
+            seq += 1;
+            out.push_str(&channel_event(name, &uuid, seq));
+        }
+        out.push_str(
+            "Content-Type: command/reply\nReply-Text: +OK Job-UUID: 1234\nJob-UUID: 1234\n\n",
+        );
+        out.push_str("Content-Type: api/response\nContent-Length: 13\n\n+OK accepted\n");
+    }
+    out.into_bytes()
+}
+
+/// the frames in `data`, a capture may end in the middle of one
+fn count_frames(data: &[u8]) -> usize {
+    let mut codec = EslCodec::new();
+    let mut buf = BytesMut::from(data);
+    let mut frames = 0;
+    while codec.decode_frame(&mut buf).unwrap().is_some() {
+        frames += 1;
+    }
+    frames
+}
+
+fn run(name: &str, data: &[u8], chunk: usize, expected: usize, parse_events: bool) {
+    let start = Instant::now();
+    let mut codec = EslCodec::new();
+    let mut buf = BytesMut::with_capacity(64 * 1024);
+    let mut frames = 0;
+    for chunk in data.chunks(chunk) {
+        buf.extend_from_slice(chunk);
+        while let Some(frame) = codec.decode_frame(&mut buf).unwrap() {
+            if parse_events {
+                let evt: Event = EventData::from(frame).into();
+                std::hint::black_box(evt);
+            } else {
+                std::hint::black_box(frame);
+            }
+            frames += 1;
+        }
+    }
+    let elapsed = start.elapsed();
+    assert_eq!(frames, expected);
+    println!(
+        "{:<28} chunk {:>6}B: {:>9.0} frames/s, {:>7.1} MB/s",
+        name,
+        chunk,
+        frames as f64 / elapsed.as_secs_f64(),
+        data.len() as f64 / elapsed.as_secs_f64() / 1e6,
+    );
+}
+
+fn main() {
+    let (source, data) = match std::env::var_os("ESL_CAPTURE") {
+        Some(path) => (
+            path.to_string_lossy().into_owned(),
+            std::fs::read(&path).expect("read ESL_CAPTURE"),
+        ),
+        None => ("generated".to_string(), synthetic()),
+    };
+    let frames = count_frames(&data);
+    println!(
+        "{}: {} frames, {:.1} MB, avg {} bytes/frame",
+        source,
+        frames,
+        data.len() as f64 / 1e6,
+        data.len() / frames.max(1)
+    );
+    for chunk in [1460, 16 * 1024, 64 * 1024] {
+        run("framing", &data, chunk, frames, false);
+    }
+    for chunk in [1460, 64 * 1024] {
+        run("framing + json events", &data, chunk, frames, true);
+    }
+}
@@ -1,17 +1,18 @@
+use esl_rs::command::Command;
 use esl_rs::event::Event;
+use esl_rs::originate::Originate;
 use esl_rs::run;
 use esl_rs::{self, Esl};
 use std::fs::File;
-use std::io::Write;
 use std::sync::Arc;
 use tokio::sync::Mutex;
-use tracing::{debug, error, info, warn};
+use tracing::{error, info};
 use tracing_subscriber::FmtSubscriber;
 
 #[tokio::main]
 async fn main() {
 
-    let mut file = File::create("app.log").unwrap();
+    let _file = File::create("app.log").unwrap();
 
     let subscriber = FmtSubscriber::builder()
         .with_max_level(tracing::Level::INFO)
@@ -55,13 +56,17 @@ async fn main() {
         .await
         .unwrap();
 
-    // custorm uuid
-    let uuid = uuid::Uuid::new_v4().to_string();
     let r = conn
         .lock()
         .await
-        .bgapi(&format!(
-            "originate [origination_caller_id_name=pc][origination_caller_id_number=1002][ignore_early_media=true][origination_uuid=444444]user/1000 &park",
+        .execute(&Command::bgapi(
+            Originate::new("user/1000")
+                .leg_var("origination_caller_id_name", "pc")
+                .leg_var("origination_caller_id_number", "1002")
+                .leg_var("ignore_early_media", true)
+                .leg_var("origination_uuid", "444444")
+                .to_string(),
+            None,
         ))
         .await
         .unwrap();
@@ -87,20 +92,27 @@ async fn handler(evt: esl_rs::event::Event, conn: Arc<Mutex<esl_rs::conn::Conn>>
 
             if let Some(leg) = v.get_var("origination_uuid") {
                 if leg == "444444" {
-                    let r = conn
+                    let _r = conn
                     .lock()
                     .await
-                    .bgapi(&format!(
-                        "originate [origination_caller_id_name=phone][origination_caller_id_number=1000][ignore_early_media=true][origination_uuid=333333]user/1004  &park",
+                    .execute(&Command::bgapi(
+                        Originate::new("user/1004")
+                            .leg_var("origination_caller_id_name", "phone")
+                            .leg_var("origination_caller_id_number", "1000")
+                            .leg_var("ignore_early_media", true)
+                            .leg_var("origination_uuid", "333333")
+                            .to_string(),
+                        None,
                     ))
                     .await
                     .unwrap();
                 } else if leg == "333333" {
                     // bridge
-                    let r = conn
-                        .lock()
+                    conn.lock()
                         .await
-                        .api(&format!("uuid_bridge {} {} both", leg, "444444"))
+                        .channel(leg)
+                        .unwrap()
+                        .bridge("444444")
                         .await
                         .unwrap();
                 }
@@ -1,11 +1,12 @@
-use esl_rs::event::Event;
+use esl_rs::command::Command;
+use esl_rs::originate::Originate;
 use esl_rs::run;
 use esl_rs::{self, Esl};
 use std::fs::File;
 use std::io::Write;
 use std::sync::Arc;
 use tokio::sync::Mutex;
-use tracing::{debug, error, info, warn};
+use tracing::{error, info};
 use tracing_subscriber::FmtSubscriber;
 
 #[tokio::main]
@@ -57,13 +58,16 @@ async fn main() {
     // custorm uuid
     let uuid = uuid::Uuid::new_v4().to_string();
 
-    for i in 0..1 {
+    for _ in 0..1 {
         let r = conn
             .lock()
             .await
-            .bgapi(&format!(
-                "originate [origination_uuid={}]user/1004 &echo",
-                uuid
+            .execute(&Command::bgapi(
+                Originate::new("user/1004")
+                    .leg_var("origination_uuid", &uuid)
+                    .app("echo", None)
+                    .to_string(),
+                None,
             ))
             .await
             .unwrap();
@@ -71,10 +75,15 @@ async fn main() {
         info!("r: {:?}", r);
 
         // 执行 eavesdrop 监听指定通话
-        let r = conn
+        let _r = conn
             .lock()
             .await
-            .bgapi(&format!("eavesdrop {}", uuid))
+            .execute(&Command::bgapi(
+                Originate::new("user/1001")
+                    .app("eavesdrop", Some(&uuid))
+                    .to_string(),
+                None,
+            ))
             .await
             .unwrap();
     }
@@ -83,7 +92,7 @@ async fn main() {
     error!("result: {:?}", result);
 }
 
-async fn handler(evt: esl_rs::event::Event, conn: Arc<Mutex<esl_rs::conn::Conn>>) {
+async fn handler(evt: esl_rs::event::Event, _conn: Arc<Mutex<esl_rs::conn::Conn>>) {
     info!("other : {:#?}", evt);
     // conn.lock().await.send("api status").await;
 }
@@ -1,11 +1,11 @@
+use esl_rs::command::{Command, EventFormat};
+use esl_rs::originate::Originate;
 use esl_rs::run;
 use esl_rs::{self, Esl};
 use std::collections::HashMap;
-use std::fs::File;
-use std::io::Write;
 use std::sync::Arc;
 use tokio::sync::Mutex;
-use tracing::{debug, error, info, warn};
+use tracing::{debug, error};
 use tracing_subscriber::FmtSubscriber;
 
 #[tokio::main]
@@ -49,21 +49,32 @@ async fn main() {
             .await;
 
         conn.lock()
-        .await
-        .send(
-            "event json CHANNEL_CREATE CHANNEL_DESTROY CHANNEL_ANSWER CHANNEL_HUGUP BACKGROUND_JOB",
-        )
-        .await
-        .unwrap();
+            .await
+            .execute(&Command::event(
+                EventFormat::Json,
+                [
+                    "CHANNEL_CREATE",
+                    "CHANNEL_DESTROY",
+                    "CHANNEL_ANSWER",
+                    "CHANNEL_HUGUP",
+                    "BACKGROUND_JOB",
+                ],
+            ))
+            .await
+            .unwrap();
 
         // custorm uuid
         let uuid = uuid::Uuid::new_v4().to_string();
         let r = conn
             .lock()
             .await
-            .bgapi(&format!(
-                "originate [ignore_early_media=true][origination_uuid={}]user/1001 &echo",
-                uuid
+            .execute(&Command::bgapi(
+                Originate::new("user/1001")
+                    .leg_var("ignore_early_media", true)
+                    .leg_var("origination_uuid", &uuid)
+                    .app("echo", None)
+                    .to_string(),
+                None,
             ))
             .await
             .unwrap();
@@ -75,6 +86,6 @@ async fn main() {
     }
 }
 
-async fn handler(evt: esl_rs::event::Event, conn: Arc<Mutex<esl_rs::conn::Conn>>) {
+async fn handler(evt: esl_rs::event::Event, _conn: Arc<Mutex<esl_rs::conn::Conn>>) {
     println!("evt: {:#?}", evt);
 }
@@ -1,10 +1,10 @@
+use esl_rs::command::Command;
+use esl_rs::originate::Originate;
 use esl_rs::run;
 use esl_rs::{self, Esl};
-use std::fs::File;
-use std::io::Write;
 use std::sync::Arc;
 use tokio::sync::Mutex;
-use tracing::{debug, error, info, warn};
+use tracing::{debug, error};
 use tracing_subscriber::FmtSubscriber;
 
 #[tokio::main]
@@ -58,9 +58,13 @@ async fn main() {
     let r = conn
         .lock()
         .await
-        .bgapi(&format!(
-            "originate [ignore_early_media=true][origination_uuid={}]user/1001 &echo",
-            uuid
+        .execute(&Command::bgapi(
+            Originate::new("user/1001")
+                .leg_var("ignore_early_media", true)
+                .leg_var("origination_uuid", &uuid)
+                .app("echo", None)
+                .to_string(),
+            None,
         ))
         .await
         .unwrap();
@@ -72,5 +76,7 @@ async fn main() {
 
 async fn handler(evt: esl_rs::event::Event, conn: Arc<Mutex<esl_rs::conn::Conn>>) {
     println!("evt: {:#?}", evt);
-    conn.lock().await.send("api status").await;
+    if let Err(e) = conn.lock().await.execute(&Command::api("status")).await {
+        error!("send error: {}", e);
+    }
 }
@@ -0,0 +1,366 @@
+//! synchronous client over `std::net::TcpStream`, no async runtime needed
+//!
+//! ```no_run
+//! use esl_rs::blocking::Conn;
+//!
+//! let mut conn = Conn::inbound("127.0.0.1:8021", "ClueCon").unwrap();
+//! println!("{}", conn.api("status").unwrap());
+//! conn.subscribe(&["CHANNEL_CREATE", "CHANNEL_HANGUP_COMPLETE"]).unwrap();
+//! for evt in conn.events() {
+//!     println!("{}", evt.unwrap());
+//! }
+//! ```
+
+use std::{
+    collections::VecDeque,
+    io::{ErrorKind, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::{Duration, Instant},
+};
+
+use tracing::debug;
+
+use crate::{
+    codec::Frame,
+    command::{command_line, terminate, Command, EventFormat},
+    error::{EslError, Result},
+    event::{Event, EventData},
+    protocol::{Output, Protocol, RequestId, State},
+    reply::{api_result, reply_result, CommandReply},
+};
+
+pub struct Conn {
+    stream: TcpStream,
+    protocol: Protocol,
+    events: VecDeque<Event>,
+    replies: VecDeque<(RequestId, Frame)>,
+    read_buf: Box<[u8]>,
+}
+
+impl Conn {
+    /// connect and authenticate
+    pub fn inbound(addr: impl ToSocketAddrs, password: impl ToString) -> Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        let mut conn = Self {
+            stream,
+            protocol: Protocol::new(password),
+            events: VecDeque::new(),
+            replies: VecDeque::new(),
+            read_buf: vec![0; 64 * 1024].into_boxed_slice(),
+        };
+        while !conn.protocol.is_ready() {
+            conn.pump()?;
+        }
+        debug!("auth success");
+        Ok(conn)
+    }
+
+    /// bound how long a read may block, `None` blocks forever.
+    /// a read that times out fails with [`EslError::Timeout`], a command
+    /// waiting for its reply is given up like with [`Self::send_timeout`]
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        self.stream.set_read_timeout(timeout)?;
+        Ok(())
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.protocol.state() != State::Closed
+    }
+
+    /// send a raw command and wait for its `command/reply` or `api/response`,
+    /// events arriving meanwhile are kept for [`Self::recv`]
+    ///
+    /// the command is written as it is, only a blank line is rejected, see
+    /// [`crate::conn::Conn::send`]
+    pub fn send(&mut self, command: &str) -> Result<Frame> {
+        let id = self.protocol.send_raw(terminate(command)?.into());
+        self.wait(command, id)
+    }
+
+    /// like [`Self::send`], failing with [`EslError::Timeout`] when no reply
+    /// arrives in time. the late reply is discarded when it comes
+    pub fn send_timeout(&mut self, command: &str, timeout: Duration) -> Result<Frame> {
+        let id = self.protocol.send_raw(terminate(command)?.into());
+        self.wait_timeout(command, id, timeout)
+    }
+
+    /// send a [`Command`], its arguments are checked before anything is written
+    pub fn execute(&mut self, command: &Command) -> Result<Frame> {
+        let wire = command.encode()?;
+        let label = command_line(&wire).to_string();
+        let id = self.protocol.send_raw(wire.into());
+        self.wait(&label, id)
+    }
+
+    /// like [`Self::execute`], giving up after `timeout`
+    pub fn execute_timeout(&mut self, command: &Command, timeout: Duration) -> Result<Frame> {
+        let wire = command.encode()?;
+        let label = command_line(&wire).to_string();
+        let id = self.protocol.send_raw(wire.into());
+        self.wait_timeout(&label, id, timeout)
+    }
+
+    /// run an api command and return its output
+    pub fn api(&mut self, command: &str) -> Result<String> {
+        api_result(self.execute(&Command::api(command))?)
+    }
+
+    /// run an api command, giving up after `timeout`
+    pub fn api_timeout(&mut self, command: &str, timeout: Duration) -> Result<String> {
+        api_result(self.execute_timeout(&Command::api(command), timeout)?)
+    }
+
+    /// return custom job-uuid, the result arrives as a BACKGROUND_JOB event
+    pub fn bgapi(&mut self, command: &str) -> Result<String> {
+        let uuid = uuid::Uuid::new_v4().to_string();
+        reply_result(self.execute(&Command::bgapi(command, Some(uuid.clone())))?)?;
+        Ok(uuid)
+    }
+
+    /// subscribe events
+    /// only support json format
+    pub fn subscribe(&mut self, events: &[&str]) -> Result<()> {
+        let command = Command::event(EventFormat::Json, events.iter().copied());
+        reply_result(self.execute(&command)?)?;
+        Ok(())
+    }
+
+    pub fn subscribe_all(&mut self) -> Result<()> {
+        reply_result(self.execute(&Command::event(EventFormat::Json, ["all"]))?)?;
+        Ok(())
+    }
+
+    pub fn unsubscribe(&mut self, events: &[&str]) -> Result<()> {
+        reply_result(self.execute(&Command::nixevent(events.iter().copied()))?)?;
+        Ok(())
+    }
+
+    pub fn unsubscribe_all(&mut self) -> Result<()> {
+        reply_result(self.execute(&Command::nixevent(["all"]))?)?;
+        Ok(())
+    }
+
+    /// wait for the next event
+    pub fn recv(&mut self) -> Result<Event> {
+        loop {
+            if let Some(evt) = self.events.pop_front() {
+                return Ok(evt);
+            }
+            self.pump()?;
+        }
+    }
+
+    /// iterate events until the connection closes
+    pub fn events(&mut self) -> Events<'_> {
+        Events { conn: self }
+    }
+
+    /// send a raw command answered by `command/reply`, `-ERR` replies are
+    /// errors. checked like [`Self::send`]
+    pub fn command(&mut self, command: &str) -> Result<CommandReply> {
+        reply_result(self.send(command)?)
+    }
+
+    fn wait(&mut self, command: &str, id: RequestId) -> Result<Frame> {
+        loop {
+            if let Some(frame) = self.take_reply(id) {
+                return Ok(frame);
+            }
+            match self.pump() {
+                Ok(()) => {}
+                // the read timeout expired, the late reply must not go to the next command
+                Err(EslError::Timeout(_)) => {
+                    self.protocol.cancel(id);
+                    return Err(EslError::Timeout(command.to_string()));
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn wait_timeout(&mut self, command: &str, id: RequestId, timeout: Duration) -> Result<Frame> {
+        let deadline = Instant::now() + timeout;
+        let read_timeout = self.stream.read_timeout()?;
+        let result = loop {
+            if let Some(frame) = self.take_reply(id) {
+                break Ok(frame);
+            }
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                break Err(EslError::Timeout(command.to_string()));
+            }
+            // a zero read timeout is rejected, `left` is never zero here
+            if let Err(e) = self.stream.set_read_timeout(Some(left)) {
+                break Err(e.into());
+            }
+            match self.pump() {
+                Ok(()) => {}
+                Err(EslError::Timeout(_)) => break Err(EslError::Timeout(command.to_string())),
+                Err(e) => break Err(e),
+            }
+        };
+        if let Err(EslError::Timeout(_)) = result {
+            self.protocol.cancel(id);
+        }
+        self.stream.set_read_timeout(read_timeout)?;
+        result
+    }
+
+    fn take_reply(&mut self, id: RequestId) -> Option<Frame> {
+        let i = self.replies.iter().position(|(reply, _)| *reply == id)?;
+        self.replies.remove(i).map(|(_, frame)| frame)
+    }
+
+    /// write what is queued, then read once and process it
+    fn pump(&mut self) -> Result<()> {
+        while let Some(bytes) = self.protocol.poll_transmit() {
+            self.stream.write_all(&bytes)?;
+        }
+        if self.protocol.state() == State::Closed {
+            return Err(EslError::ConnectionError("disconnected".to_string()));
+        }
+        let n = match self.stream.read(&mut self.read_buf) {
+            Ok(n) => n,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                return Err(EslError::Timeout("read".to_string()));
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if n == 0 {
+            self.protocol.close();
+            return Err(EslError::ConnectionError("connection closed".to_string()));
+        }
+        self.protocol.feed(&self.read_buf[..n])?;
+        while let Some(output) = self.protocol.poll_output() {
+            match output {
+                Output::Authenticated => {}
+                Output::AuthFailed(e) => return Err(e),
+                Output::Reply { id, frame } => self.replies.push_back((id, frame)),
+                Output::Event(evt) => self.events.push_back(evt),
+                Output::Disconnected(frame) => self.events.push_back(EventData::from(frame).into()),
+            }
+        }
+        // the auth reply may have released held commands
+        while let Some(bytes) = self.protocol.poll_transmit() {
+            self.stream.write_all(&bytes)?;
+        }
+        Ok(())
+    }
+}
+
+/// blocking iterator over events, see [`Conn::events`]
+pub struct Events<'a> {
+    conn: &'a mut Conn,
+}
+
+impl Iterator for Events<'_> {
+    type Item = Result<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.conn.events.is_empty() && !self.conn.is_connected() {
+            return None;
+        }
+        match self.conn.recv() {
+            Ok(evt) => Some(Ok(evt)),
+            Err(EslError::ConnectionError(_)) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        io::{BufRead, BufReader},
+        net::TcpListener,
+        thread,
+    };
+
+    #[test]
+    fn api_and_events() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut lines = BufReader::new(stream.try_clone().unwrap()).lines();
+            let mut command = || {
+                let line = lines.next().unwrap().unwrap();
+                assert_eq!(lines.next().unwrap().unwrap(), "");
+                line
+            };
+            stream.write_all(b"Content-Type: auth/request\n\n").unwrap();
+            assert_eq!(command(), "auth ClueCon");
+            stream
+                .write_all(b"Content-Type: command/reply\nReply-Text: +OK accepted\n\n")
+                .unwrap();
+            assert_eq!(command(), "api status");
+            // an event sneaks in before the response
+            stream
+                .write_all(
+                    b"Content-Length: 27\nContent-Type: text/event-json\n\n{\"Event-Name\":\"HEARTBEAT\"}\n\
+                      Content-Type: api/response\nContent-Length: 3\n\nUP\n",
+                )
+                .unwrap();
+            assert_eq!(command(), "api nope");
+            stream
+                .write_all(b"Content-Type: api/response\nContent-Length: 29\n\n-ERR nope Command not found!\n")
+                .unwrap();
+            assert_eq!(command(), "event json CHANNEL_CREATE");
+            stream
+                .write_all(
+                    b"Content-Type: command/reply\nReply-Text: +OK event listener enabled json\n\n\
+                      Content-Length: 32\nContent-Type: text/event-json\n\n{\"Event-Name\":\"CHANNEL_CREATE\"}\n",
+                )
+                .unwrap();
+        });
+
+        let mut conn = Conn::inbound(addr, "ClueCon").unwrap();
+        assert_eq!(conn.api("status").unwrap(), "UP\n");
+        assert!(matches!(conn.api("nope"), Err(EslError::InvalidCommand(_))));
+        conn.subscribe(&["CHANNEL_CREATE"]).unwrap();
+        let names: Vec<_> = conn
+            .events()
+            .map(|evt| evt.unwrap().get_event_name().unwrap())
+            .collect();
+        assert_eq!(names, ["HEARTBEAT", "CHANNEL_CREATE"]);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn read_timeout_cancels() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut lines = BufReader::new(stream.try_clone().unwrap()).lines();
+            stream.write_all(b"Content-Type: auth/request\n\n").unwrap();
+            lines.nth(1).unwrap().unwrap();
+            stream
+                .write_all(b"Content-Type: command/reply\nReply-Text: +OK accepted\n\n")
+                .unwrap();
+            // answer `api slow` only once `api status` was sent
+            assert_eq!(lines.next().unwrap().unwrap(), "api slow");
+            assert_eq!(lines.nth(1).unwrap().unwrap(), "api status");
+            stream
+                .write_all(
+                    b"Content-Type: api/response\nContent-Length: 5\n\nslow\n\
+                      Content-Type: api/response\nContent-Length: 3\n\nUP\n",
+                )
+                .unwrap();
+            lines.next();
+        });
+
+        let mut conn = Conn::inbound(addr, "ClueCon").unwrap();
+        conn.set_read_timeout(Some(Duration::from_millis(50)))
+            .unwrap();
+        assert_eq!(
+            conn.api("slow").unwrap_err(),
+            EslError::Timeout("api slow".to_string())
+        );
+        conn.set_read_timeout(None).unwrap();
+        assert_eq!(conn.api("status").unwrap(), "UP\n");
+        drop(conn);
+        server.join().unwrap();
+    }
+}
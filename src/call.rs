@@ -0,0 +1,526 @@
+//! grouping channels into calls
+//!
+//! freeswitch only knows channels. a [`CallGraph`] follows CHANNEL_CREATE,
+//! CHANNEL_BRIDGE/UNBRIDGE and the hangup events, links legs through
+//! `Other-Leg-Unique-ID`, `Channel-Call-UUID` and `Bridge-A/B-Unique-ID`, and
+//! reads `transfer_history` to record transfers. a [`Call`] ends when its last
+//! leg is destroyed.
+//!
+//! like [`crate::sequence::SequenceTracker`] it is a plain state machine, feed
+//! it every event, e.g. from [`crate::conn::Conn::events`].
+
+use std::{
+    collections::{HashMap, HashSet},
+    time::SystemTime,
+};
+
+use crate::{
+    event::{Event, EventData},
+    hangup::HangupCause,
+    var::split_list,
+};
+
+/// how a leg was transferred, from `transfer_history`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferKind {
+    /// `bl_xfer`, sent to a dialplan extension
+    Blind,
+    /// `att_xfer`, bridged to the party consulted first
+    Attended,
+    /// `uuid_br`, bridged to another channel with `uuid_bridge`
+    UuidBridge,
+}
+
+/// one step in the life of a call
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CallEvent {
+    LegCreated {
+        uuid: String,
+    },
+    Answered {
+        uuid: String,
+    },
+    Bridged {
+        a: String,
+        b: String,
+    },
+    Unbridged {
+        a: String,
+        b: String,
+    },
+    Transferred {
+        uuid: String,
+        kind: TransferKind,
+        /// `exten/dialplan/context` or the uuid bridged to
+        target: String,
+    },
+    /// another call was folded into this one, e.g. by an attended transfer
+    Merged {
+        call: String,
+    },
+    LegHangup {
+        uuid: String,
+        cause: Option<HangupCause>,
+    },
+}
+
+/// a [`CallEvent`] and when freeswitch fired it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallHistory {
+    pub time: Option<SystemTime>,
+    pub event: CallEvent,
+}
+
+/// one conversation and every leg it involved
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Call {
+    /// the uuid of the leg that started the call
+    pub id: String,
+    /// every leg, in the order they joined
+    pub legs: Vec<String>,
+    /// legs not destroyed yet
+    pub active: HashSet<String>,
+    /// pairs currently bridged
+    pub bridges: Vec<(String, String)>,
+    /// in the order freeswitch fired the events
+    pub history: Vec<CallHistory>,
+}
+
+impl Call {
+    fn new(id: String) -> Self {
+        Self {
+            id,
+            ..Default::default()
+        }
+    }
+
+    fn add_leg(&mut self, uuid: &str) {
+        if !self.legs.iter().any(|leg| leg == uuid) {
+            self.legs.push(uuid.to_string());
+        }
+        self.active.insert(uuid.to_string());
+    }
+
+    pub fn is_bridged(&self) -> bool {
+        !self.bridges.is_empty()
+    }
+
+    pub fn is_ended(&self) -> bool {
+        self.active.is_empty()
+    }
+}
+
+/// what a pushed event did to the graph
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CallChange {
+    Started(String),
+    Updated(String),
+    /// the last leg is gone, the call left the graph
+    Ended(Call),
+}
+
+/// calls by id, see the [module docs](self)
+#[derive(Debug, Default)]
+pub struct CallGraph {
+    calls: HashMap<String, Call>,
+    /// leg uuid to call id
+    legs: HashMap<String, String>,
+    /// `transfer_history` entries already recorded, per leg
+    transfers: HashMap<String, usize>,
+}
+
+impl CallGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn call(&self, id: &str) -> Option<&Call> {
+        self.calls.get(id)
+    }
+
+    /// the call a channel belongs to
+    pub fn call_of(&self, uuid: &str) -> Option<&Call> {
+        self.calls.get(self.legs.get(uuid)?)
+    }
+
+    pub fn calls(&self) -> impl Iterator<Item = &Call> {
+        self.calls.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.calls.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.calls.is_empty()
+    }
+
+    /// update the graph from one event, other events are ignored
+    pub fn push(&mut self, evt: &Event) -> Vec<CallChange> {
+        let Some(uuid) = evt.get_body_by_key("Unique-ID") else {
+            return Vec::new();
+        };
+        let time = evt.event_time();
+        let mut changes = Vec::new();
+        match evt {
+            Event::ChannelCreate(data) => {
+                let linked = [
+                    data.get_body_by_key("Other-Leg-Unique-ID"),
+                    data.get_channel_call_uuid(),
+                ]
+                .into_iter()
+                .flatten()
+                .find_map(|leg| self.legs.get(&leg).cloned());
+                let id = match linked {
+                    Some(id) => id,
+                    None => {
+                        self.calls.insert(uuid.clone(), Call::new(uuid.clone()));
+                        changes.push(CallChange::Started(uuid.clone()));
+                        uuid.clone()
+                    }
+                };
+                self.join(&id, &uuid);
+                self.record(&id, time, CallEvent::LegCreated { uuid: uuid.clone() });
+                changes.push(CallChange::Updated(id));
+            }
+            Event::ChannelAnswer(_) => {
+                if let Some(id) = self.legs.get(&uuid).cloned() {
+                    self.record(&id, time, CallEvent::Answered { uuid: uuid.clone() });
+                    changes.push(CallChange::Updated(id));
+                }
+            }
+            Event::ChannelBridge(data) | Event::ChannelUnbridge(data) => {
+                let a = data
+                    .get_body_by_key("Bridge-A-Unique-ID")
+                    .unwrap_or_else(|| uuid.clone());
+                let Some(b) = data
+                    .get_body_by_key("Bridge-B-Unique-ID")
+                    .or_else(|| data.get_body_by_key("Other-Leg-Unique-ID"))
+                else {
+                    return changes;
+                };
+                let bridged = matches!(evt, Event::ChannelBridge(_));
+                let id = self.link(&a, &b, time, &mut changes);
+                if let Some(call) = self.calls.get_mut(&id) {
+                    let pair = (a.clone(), b.clone());
+                    call.bridges.retain(|p| *p != pair);
+                    if bridged {
+                        call.bridges.push(pair);
+                    }
+                }
+                let event = match bridged {
+                    true => CallEvent::Bridged { a, b },
+                    false => CallEvent::Unbridged { a, b },
+                };
+                self.record(&id, time, event);
+                changes.push(CallChange::Updated(id));
+            }
+            Event::ChannelHangup(data) | Event::ChannelHangupComplete(data) => {
+                if let Some(id) = self.legs.get(&uuid).cloned() {
+                    let hangup_recorded = self.calls.get(&id).is_some_and(|call| {
+                        call.history.iter().any(|h| {
+                            matches!(&h.event, CallEvent::LegHangup { uuid: leg, .. } if *leg == uuid)
+                        })
+                    });
+                    if !hangup_recorded {
+                        let cause = data.hangup_cause();
+                        self.record(
+                            &id,
+                            time,
+                            CallEvent::LegHangup {
+                                uuid: uuid.clone(),
+                                cause,
+                            },
+                        );
+                        changes.push(CallChange::Updated(id));
+                    }
+                }
+            }
+            Event::ChannelDestroy(_) => {
+                self.transfers.remove(&uuid);
+                if let Some(id) = self.legs.remove(&uuid) {
+                    if let Some(call) = self.calls.get_mut(&id) {
+                        call.active.remove(&uuid);
+                        call.bridges.retain(|(a, b)| *a != uuid && *b != uuid);
+                        if call.is_ended() {
+                            if let Some(call) = self.calls.remove(&id) {
+                                changes.push(CallChange::Ended(call));
+                            }
+                            return changes;
+                        }
+                    }
+                    changes.push(CallChange::Updated(id));
+                }
+                return changes;
+            }
+            _ => {}
+        }
+        self.transfers_of(&uuid, evt, &mut changes);
+        changes
+    }
+
+    fn join(&mut self, id: &str, uuid: &str) {
+        self.legs.insert(uuid.to_string(), id.to_string());
+        if let Some(call) = self.calls.get_mut(id) {
+            call.add_leg(uuid);
+        }
+    }
+
+    /// put `a` and `b` in the same call, merging their calls if needed
+    fn link(
+        &mut self,
+        a: &str,
+        b: &str,
+        time: Option<SystemTime>,
+        changes: &mut Vec<CallChange>,
+    ) -> String {
+        let (call_a, call_b) = (self.legs.get(a).cloned(), self.legs.get(b).cloned());
+        match (call_a, call_b) {
+            (Some(ia), Some(ib)) if ia != ib => {
+                // the call of `a` survives
+                if let Some(other) = self.calls.remove(&ib) {
+                    for leg in &other.legs {
+                        if other.active.contains(leg) {
+                            self.legs.insert(leg.clone(), ia.clone());
+                        }
+                    }
+                    if let Some(call) = self.calls.get_mut(&ia) {
+                        for leg in &other.legs {
+                            if !call.legs.contains(leg) {
+                                call.legs.push(leg.clone());
+                            }
+                        }
+                        call.active.extend(other.active);
+                        call.bridges.extend(other.bridges);
+                        call.history.extend(other.history);
+                        call.history.sort_by_key(|h| h.time);
+                    }
+                    self.record(&ia, time, CallEvent::Merged { call: ib });
+                }
+                ia
+            }
+            (Some(id), _) | (_, Some(id)) => {
+                self.join(&id, a);
+                self.join(&id, b);
+                id
+            }
+            (None, None) => {
+                let id = a.to_string();
+                self.calls.insert(id.clone(), Call::new(id.clone()));
+                changes.push(CallChange::Started(id.clone()));
+                self.join(&id, a);
+                self.join(&id, b);
+                id
+            }
+        }
+    }
+
+    /// record entries of `transfer_history` not seen before
+    fn transfers_of(&mut self, uuid: &str, data: &EventData, changes: &mut Vec<CallChange>) {
+        let Some(history) = data.get_var("transfer_history") else {
+            return;
+        };
+        let Some(id) = self.legs.get(uuid).cloned() else {
+            return;
+        };
+        let entries: Vec<_> = split_list(&history)
+            .filter(|entry| !entry.is_empty())
+            .map(str::to_string)
+            .collect();
+        let seen = self
+            .transfers
+            .insert(uuid.to_string(), entries.len())
+            .unwrap_or(0);
+        if entries.len() <= seen {
+            return;
+        }
+        for entry in &entries[seen..] {
+            // epoch:uuid:kind:target
+            let mut parts = entry.splitn(4, ':');
+            let (Some(_epoch), Some(_uuid), Some(kind), Some(target)) =
+                (parts.next(), parts.next(), parts.next(), parts.next())
+            else {
+                continue;
+            };
+            let kind = match kind {
+                "bl_xfer" => TransferKind::Blind,
+                "att_xfer" => TransferKind::Attended,
+                "uuid_br" => TransferKind::UuidBridge,
+                _ => continue,
+            };
+            let event = CallEvent::Transferred {
+                uuid: uuid.to_string(),
+                kind,
+                target: target.to_string(),
+            };
+            self.record(&id, data.event_time(), event);
+        }
+        changes.push(CallChange::Updated(id));
+    }
+
+    /// add to the history of `id`, behind the entries not fired later
+    fn record(&mut self, id: &str, time: Option<SystemTime>, event: CallEvent) {
+        if let Some(call) = self.calls.get_mut(id) {
+            // events of different legs are not always delivered in order
+            let at = call
+                .history
+                .iter()
+                .rposition(|h| h.time.is_none() || time.is_none() || h.time <= time)
+                .map_or(0, |i| i + 1);
+            call.history.insert(at, CallHistory { time, event });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::json_event;
+
+    #[test]
+    fn bridge_and_transfer() {
+        let mut graph = CallGraph::new();
+        let changes = graph.push(&json_event(
+            serde_json::json!({"Event-Name": "CHANNEL_CREATE", "Unique-ID": "a"}),
+        ));
+        assert_eq!(changes[0], CallChange::Started("a".to_string()));
+        graph.push(&json_event(serde_json::json!({"Event-Name": "CHANNEL_CREATE", "Unique-ID": "b", "Other-Leg-Unique-ID": "a"})));
+        graph.push(&json_event(serde_json::json!({"Event-Name": "CHANNEL_BRIDGE", "Unique-ID": "a", "Bridge-A-Unique-ID": "a", "Bridge-B-Unique-ID": "b"})));
+        assert_eq!(graph.len(), 1);
+        assert!(graph.call("a").unwrap().is_bridged());
+
+        // an unrelated call gets attended-transferred into this one
+        graph.push(&json_event(
+            serde_json::json!({"Event-Name": "CHANNEL_CREATE", "Unique-ID": "c"}),
+        ));
+        assert_eq!(graph.len(), 2);
+        graph.push(&json_event(serde_json::json!({"Event-Name": "CHANNEL_BRIDGE", "Unique-ID": "b", "Bridge-A-Unique-ID": "b", "Bridge-B-Unique-ID": "c", "variable_transfer_history": "1700000000:b:att_xfer:c"})));
+        assert_eq!(graph.len(), 1);
+        let call = graph.call_of("c").unwrap();
+        assert_eq!(call.id, "a");
+        assert_eq!(call.legs, ["a", "b", "c"]);
+        assert!(call.history.iter().any(|h| matches!(
+            &h.event,
+            CallEvent::Transferred { kind: TransferKind::Attended, target, .. } if target == "c"
+        )));
+
+        for leg in ["a", "b"] {
+            graph.push(&json_event(
+                serde_json::json!({"Event-Name": "CHANNEL_DESTROY", "Unique-ID": leg}),
+            ));
+        }
+        let changes = graph.push(&json_event(
+            serde_json::json!({"Event-Name": "CHANNEL_DESTROY", "Unique-ID": "c"}),
+        ));
+        assert!(matches!(&changes[..], [CallChange::Ended(call)] if call.legs.len() == 3));
+        assert!(graph.is_empty());
+    }
+
+    /// the history of the call `uuid` is in, without the times
+    fn history(graph: &CallGraph, uuid: &str) -> Vec<CallEvent> {
+        let call = graph.call_of(uuid).unwrap();
+        call.history.iter().map(|h| h.event.clone()).collect()
+    }
+
+    #[test]
+    fn unbridge() {
+        let mut graph = CallGraph::new();
+        for body in [
+            serde_json::json!({"Event-Name": "CHANNEL_CREATE", "Unique-ID": "a"}),
+            serde_json::json!({"Event-Name": "CHANNEL_CREATE", "Unique-ID": "b", "Other-Leg-Unique-ID": "a"}),
+            serde_json::json!({"Event-Name": "CHANNEL_BRIDGE", "Unique-ID": "a", "Bridge-A-Unique-ID": "a", "Bridge-B-Unique-ID": "b"}),
+        ] {
+            graph.push(&json_event(body));
+        }
+        assert!(graph.call("a").unwrap().is_bridged());
+
+        // the unbridge only names the other leg
+        let changes = graph.push(&json_event(serde_json::json!({"Event-Name": "CHANNEL_UNBRIDGE", "Unique-ID": "a", "Other-Leg-Unique-ID": "b"})));
+        assert_eq!(changes, [CallChange::Updated("a".to_string())]);
+        let call = graph.call("a").unwrap();
+        assert!(!call.is_bridged());
+        assert_eq!(call.active.len(), 2);
+        assert_eq!(
+            history(&graph, "b").last(),
+            Some(&CallEvent::Unbridged {
+                a: "a".to_string(),
+                b: "b".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn transfer_history_entries() {
+        let mut graph = CallGraph::new();
+        graph.push(&json_event(
+            serde_json::json!({"Event-Name": "CHANNEL_CREATE", "Unique-ID": "a"}),
+        ));
+        graph.push(&json_event(
+            serde_json::json!({"Event-Name": "CHANNEL_EXECUTE", "Unique-ID": "a",
+            "variable_transfer_history": "1700000000:a:bl_xfer:1000/XML/default"}),
+        ));
+        // every event repeats the whole history, only the new entries count
+        graph.push(&json_event(serde_json::json!({"Event-Name": "CHANNEL_EXECUTE", "Unique-ID": "a",
+            "variable_transfer_history": "ARRAY::1700000000:a:bl_xfer:1000/XML/default|:1700000005:a:uuid_xfer:x|:1700000010:a:att_xfer:c"})));
+        graph.push(&json_event(serde_json::json!({"Event-Name": "CHANNEL_EXECUTE", "Unique-ID": "a",
+            "variable_transfer_history": "ARRAY::1700000000:a:bl_xfer:1000/XML/default|:1700000005:a:uuid_xfer:x|:1700000010:a:att_xfer:c"})));
+
+        let transfer = |kind, target: &str| CallEvent::Transferred {
+            uuid: "a".to_string(),
+            kind,
+            target: target.to_string(),
+        };
+        assert_eq!(
+            history(&graph, "a")[1..],
+            [
+                transfer(TransferKind::Blind, "1000/XML/default"),
+                transfer(TransferKind::Attended, "c"),
+            ]
+        );
+    }
+
+    #[test]
+    fn hangup_before_bridge() {
+        let mut graph = CallGraph::new();
+        let event = |name: &str, uuid: &str, micros: u64| {
+            json_event(serde_json::json!({"Event-Name": name, "Unique-ID": uuid,
+                "Event-Date-Timestamp": micros.to_string(), "Hangup-Cause": "NORMAL_CLEARING"}))
+        };
+        graph.push(&event("CHANNEL_CREATE", "a", 1_000_000));
+        graph.push(&event("CHANNEL_CREATE", "b", 2_000_000));
+        graph.push(&event("CHANNEL_HANGUP", "b", 4_000_000));
+        assert_eq!(graph.len(), 2);
+
+        // the bridge was fired before the hangup but is delivered after it
+        let bridge = json_event(
+            serde_json::json!({"Event-Name": "CHANNEL_BRIDGE", "Unique-ID": "a",
+            "Event-Date-Timestamp": "3000000", "Bridge-A-Unique-ID": "a", "Bridge-B-Unique-ID": "b"}),
+        );
+        graph.push(&bridge);
+        assert_eq!(graph.len(), 1);
+        graph.push(&event("CHANNEL_HANGUP_COMPLETE", "b", 4_100_000));
+        let hangup_b = CallEvent::LegHangup {
+            uuid: "b".to_string(),
+            cause: Some(HangupCause::NormalClearing),
+        };
+        let history = history(&graph, "a");
+        assert_eq!(history.iter().filter(|e| **e == hangup_b).count(), 1);
+        assert!(matches!(history[2], CallEvent::Merged { .. }));
+        assert!(matches!(history[3], CallEvent::Bridged { .. }));
+        assert_eq!(history[4], hangup_b);
+
+        // the call ends with the last destroy, whatever order they come in
+        let changes = graph.push(&event("CHANNEL_DESTROY", "b", 4_200_000));
+        assert_eq!(changes, [CallChange::Updated("a".to_string())]);
+        assert!(!graph.call("a").unwrap().is_bridged());
+        graph.push(&event("CHANNEL_HANGUP", "a", 5_000_000));
+        let changes = graph.push(&event("CHANNEL_DESTROY", "a", 5_100_000));
+        let [CallChange::Ended(call)] = &changes[..] else {
+            panic!("unexpected {:?}", changes);
+        };
+        assert_eq!(call.legs, ["a", "b"]);
+        assert!(matches!(call.history.last().map(|h| &h.event),
+            Some(CallEvent::LegHangup { uuid, .. }) if uuid == "a"));
+        assert!(graph.is_empty());
+    }
+}
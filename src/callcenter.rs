@@ -0,0 +1,793 @@
+//! mod_callcenter: `callcenter_config` commands and `callcenter::info` events
+//!
+//! [`Callcenter`] wraps the agent, tier and queue commands and parses their
+//! `|` separated list outputs into rows. [`CallcenterEvent`] types the
+//! `callcenter::info` CUSTOM events, subscribe them with [`CALLCENTER_EVENTS`].
+//!
+//! ```no_run
+//! # async fn demo(conn: esl_rs::conn::Conn) -> esl_rs::error::Result<()> {
+//! use esl_rs::callcenter::{AgentStatus, AgentType};
+//!
+//! let cc = conn.callcenter();
+//! cc.agent_add("1000@default", AgentType::Callback).await?;
+//! cc.agent_set_status("1000@default", AgentStatus::Available).await?;
+//! cc.tier_add("support@default", "1000@default", 1, 1).await?;
+//! for member in cc.queue_members("support@default").await? {
+//!     println!("{} waiting since {:?}", member.cid_number, member.joined_epoch);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{fmt, str::FromStr};
+
+use serde::{de::DeserializeOwned, Deserialize, Deserializer};
+
+use crate::{
+    channel::{quote, token},
+    command::Command,
+    conn::Conn,
+    de::{from_fields, FieldValue},
+    error::Result,
+    event::Event,
+    hangup::HangupCause,
+    reply::api_result,
+};
+
+/// what to subscribe for [`CallcenterEvent`]
+pub const CALLCENTER_EVENTS: [&str; 2] = ["CUSTOM", "callcenter::info"];
+
+const SUBCLASS: &str = "callcenter::info";
+
+/// an enum of the strings mod_callcenter uses, with `Other` for the rest
+macro_rules! names {
+    ($(#[$meta:meta])* $name:ident { $($(#[$vmeta:meta])* $variant:ident = $value:literal,)* }) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+        pub enum $name {
+            $($(#[$vmeta])* $variant,)*
+            Other(String),
+        }
+
+        impl $name {
+            pub fn as_str(&self) -> &str {
+                match self {
+                    $(Self::$variant => $value,)*
+                    Self::Other(other) => other,
+                }
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = std::convert::Infallible;
+
+            fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+                Ok(match s.trim() {
+                    $($value => Self::$variant,)*
+                    other => Self::Other(other.to_string()),
+                })
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(self.as_str())
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(d: D) -> std::result::Result<Self, D::Error> {
+                let s = String::deserialize(d)?;
+                Ok(s.parse().unwrap_or_else(|never| match never {}))
+            }
+        }
+    };
+}
+
+names! {
+    /// whether an agent takes calls
+    AgentStatus {
+        #[default]
+        LoggedOut = "Logged Out",
+        Available = "Available",
+        /// available, but leaves the queue after each call
+        AvailableOnDemand = "Available (On Demand)",
+        OnBreak = "On Break",
+    }
+}
+
+names! {
+    /// what an agent is doing
+    AgentState {
+        #[default]
+        Idle = "Idle",
+        Waiting = "Waiting",
+        Receiving = "Receiving",
+        InQueueCall = "In a queue call",
+    }
+}
+
+names! {
+    /// an agent's state in one queue
+    TierState {
+        #[default]
+        Unknown = "Unknown",
+        NoAnswer = "No Answer",
+        Ready = "Ready",
+        Offering = "Offering",
+        /// bridged to a caller
+        ActiveInbound = "Active Inbound",
+        Standby = "Standby",
+    }
+}
+
+names! {
+    /// how an agent is reached
+    AgentType {
+        /// called at its contact for each call
+        #[default]
+        Callback = "callback",
+        /// already on a channel, bridged to callers
+        UuidStandby = "uuid-standby",
+    }
+}
+
+/// an agent setting for [`Callcenter::agent_set`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AgentSetting {
+    /// the dial string, e.g. `user/1000`
+    Contact(String),
+    /// unix time before which the agent gets no calls
+    ReadyTime(u64),
+    RejectDelayTime(u32),
+    BusyDelayTime(u32),
+    NoAnswerDelayTime(u32),
+    MaxNoAnswer(u32),
+    WrapUpTime(u32),
+}
+
+/// what [`Callcenter::queue_count`] counts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueueCount {
+    Agents,
+    Members,
+    Tiers,
+}
+
+/// a row of `agent list`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct AgentRow {
+    pub name: String,
+    pub instance_id: String,
+    pub uuid: String,
+    #[serde(rename = "type")]
+    pub agent_type: AgentType,
+    pub contact: String,
+    pub status: AgentStatus,
+    pub state: AgentState,
+    pub max_no_answer: Option<u32>,
+    pub wrap_up_time: Option<u32>,
+    pub reject_delay_time: Option<u32>,
+    pub busy_delay_time: Option<u32>,
+    pub no_answer_delay_time: Option<u32>,
+    pub last_bridge_start: Option<u64>,
+    pub last_bridge_end: Option<u64>,
+    pub last_offered_call: Option<u64>,
+    pub last_status_change: Option<u64>,
+    pub no_answer_count: Option<u32>,
+    pub calls_answered: Option<u32>,
+    pub talk_time: Option<u64>,
+    pub ready_time: Option<u64>,
+}
+
+/// a row of `tier list`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct TierRow {
+    pub queue: String,
+    pub agent: String,
+    pub state: TierState,
+    pub level: Option<u32>,
+    pub position: Option<u32>,
+}
+
+/// a row of `queue list`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct QueueRow {
+    pub name: String,
+    pub strategy: String,
+    pub moh_sound: String,
+    pub time_base_score: String,
+    pub tier_rules_apply: Option<bool>,
+    pub tier_rule_wait_second: Option<u32>,
+    pub tier_rule_wait_multiply_level: Option<bool>,
+    pub tier_rule_no_agent_no_wait: Option<bool>,
+    pub discard_abandoned_after: Option<u32>,
+    pub abandoned_resume_allowed: Option<bool>,
+    pub max_wait_time: Option<u32>,
+    pub max_wait_time_with_no_agent: Option<u32>,
+    pub record_template: String,
+    pub calls_answered: Option<u32>,
+    pub calls_abandoned: Option<u32>,
+}
+
+/// a row of `queue list members`, a caller waiting or being served
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct QueueMemberRow {
+    pub queue: String,
+    pub instance_id: String,
+    pub uuid: String,
+    pub session_uuid: String,
+    pub cid_number: String,
+    pub cid_name: String,
+    pub system_epoch: Option<u64>,
+    pub joined_epoch: Option<u64>,
+    pub rejoined_epoch: Option<u64>,
+    pub bridge_epoch: Option<u64>,
+    pub abandoned_epoch: Option<u64>,
+    pub base_score: Option<i64>,
+    pub skill_score: Option<i64>,
+    pub serving_agent: String,
+    pub serving_system: String,
+    /// `Waiting`, `Trying`, `Answered` or `Abandoned`
+    pub state: String,
+    pub score: Option<i64>,
+}
+
+/// parse a `|` separated list with a header line, ending in `+OK`
+pub fn parse_list<T: DeserializeOwned>(text: &str) -> Result<Vec<T>> {
+    let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+    let Some(header) = lines.next() else {
+        return Ok(Vec::new());
+    };
+    let columns: Vec<_> = header.split('|').collect();
+    lines
+        .take_while(|line| line.trim() != "+OK")
+        .map(|line| {
+            let fields = columns
+                .iter()
+                .zip(line.split('|'))
+                .map(|(column, value)| (*column, FieldValue::Str(value)));
+            from_fields(fields)
+        })
+        .collect()
+}
+
+/// `callcenter_config` on a [`Conn`], see [`Conn::callcenter`]
+#[derive(Debug, Clone)]
+pub struct Callcenter {
+    conn: Conn,
+}
+
+impl Conn {
+    pub fn callcenter(&self) -> Callcenter {
+        Callcenter { conn: self.clone() }
+    }
+}
+
+impl Callcenter {
+    pub async fn agent_add(&self, name: &str, agent_type: AgentType) -> Result<()> {
+        self.config(&["agent", "add", token(name)?, agent_type.as_str()])
+            .await?;
+        Ok(())
+    }
+
+    pub async fn agent_del(&self, name: &str) -> Result<()> {
+        self.config(&["agent", "del", token(name)?]).await?;
+        Ok(())
+    }
+
+    /// reload the agent from the database
+    pub async fn agent_reload(&self, name: &str) -> Result<()> {
+        self.config(&["agent", "reload", token(name)?]).await?;
+        Ok(())
+    }
+
+    pub async fn agent_set_status(&self, name: &str, status: AgentStatus) -> Result<()> {
+        self.config(&[
+            "agent",
+            "set",
+            "status",
+            token(name)?,
+            &quote(status.as_str())?,
+        ])
+        .await?;
+        Ok(())
+    }
+
+    pub async fn agent_set_state(&self, name: &str, state: AgentState) -> Result<()> {
+        self.config(&[
+            "agent",
+            "set",
+            "state",
+            token(name)?,
+            &quote(state.as_str())?,
+        ])
+        .await?;
+        Ok(())
+    }
+
+    pub async fn agent_set(&self, name: &str, setting: AgentSetting) -> Result<()> {
+        let (key, value) = match setting {
+            AgentSetting::Contact(contact) => ("contact", quote(&contact)?),
+            AgentSetting::ReadyTime(time) => ("ready_time", time.to_string()),
+            AgentSetting::RejectDelayTime(secs) => ("reject_delay_time", secs.to_string()),
+            AgentSetting::BusyDelayTime(secs) => ("busy_delay_time", secs.to_string()),
+            AgentSetting::NoAnswerDelayTime(secs) => ("no_answer_delay_time", secs.to_string()),
+            AgentSetting::MaxNoAnswer(count) => ("max_no_answer", count.to_string()),
+            AgentSetting::WrapUpTime(secs) => ("wrap_up_time", secs.to_string()),
+        };
+        self.config(&["agent", "set", key, token(name)?, &value])
+            .await?;
+        Ok(())
+    }
+
+    /// `agent get status`
+    pub async fn agent_status(&self, name: &str) -> Result<AgentStatus> {
+        let status = self
+            .config(&["agent", "get", "status", token(name)?])
+            .await?;
+        Ok(status.trim().parse().unwrap_or_else(|never| match never {}))
+    }
+
+    /// `agent list`, every agent
+    pub async fn agents(&self) -> Result<Vec<AgentRow>> {
+        parse_list(&self.config(&["agent", "list"]).await?)
+    }
+
+    /// `agent list <name>`
+    pub async fn agent(&self, name: &str) -> Result<Option<AgentRow>> {
+        let rows = parse_list(&self.config(&["agent", "list", token(name)?]).await?)?;
+        Ok(rows.into_iter().next())
+    }
+
+    /// put `agent` in `queue`, lower levels and positions are offered calls first
+    pub async fn tier_add(
+        &self,
+        queue: &str,
+        agent: &str,
+        level: u32,
+        position: u32,
+    ) -> Result<()> {
+        let (level, position) = (level.to_string(), position.to_string());
+        self.config(&[
+            "tier",
+            "add",
+            token(queue)?,
+            token(agent)?,
+            &level,
+            &position,
+        ])
+        .await?;
+        Ok(())
+    }
+
+    pub async fn tier_del(&self, queue: &str, agent: &str) -> Result<()> {
+        self.config(&["tier", "del", token(queue)?, token(agent)?])
+            .await?;
+        Ok(())
+    }
+
+    pub async fn tier_reload(&self, queue: &str, agent: &str) -> Result<()> {
+        self.config(&["tier", "reload", token(queue)?, token(agent)?])
+            .await?;
+        Ok(())
+    }
+
+    pub async fn tier_set_state(&self, queue: &str, agent: &str, state: TierState) -> Result<()> {
+        let state = quote(state.as_str())?;
+        self.config(&["tier", "set", "state", token(queue)?, token(agent)?, &state])
+            .await?;
+        Ok(())
+    }
+
+    pub async fn tier_set_level(&self, queue: &str, agent: &str, level: u32) -> Result<()> {
+        let level = level.to_string();
+        self.config(&["tier", "set", "level", token(queue)?, token(agent)?, &level])
+            .await?;
+        Ok(())
+    }
+
+    pub async fn tier_set_position(&self, queue: &str, agent: &str, position: u32) -> Result<()> {
+        let position = position.to_string();
+        self.config(&[
+            "tier",
+            "set",
+            "position",
+            token(queue)?,
+            token(agent)?,
+            &position,
+        ])
+        .await?;
+        Ok(())
+    }
+
+    /// `tier list`, every tier
+    pub async fn tiers(&self) -> Result<Vec<TierRow>> {
+        parse_list(&self.config(&["tier", "list"]).await?)
+    }
+
+    pub async fn queue_load(&self, queue: &str) -> Result<()> {
+        self.config(&["queue", "load", token(queue)?]).await?;
+        Ok(())
+    }
+
+    pub async fn queue_unload(&self, queue: &str) -> Result<()> {
+        self.config(&["queue", "unload", token(queue)?]).await?;
+        Ok(())
+    }
+
+    pub async fn queue_reload(&self, queue: &str) -> Result<()> {
+        self.config(&["queue", "reload", token(queue)?]).await?;
+        Ok(())
+    }
+
+    /// `queue list`, every loaded queue
+    pub async fn queues(&self) -> Result<Vec<QueueRow>> {
+        parse_list(&self.config(&["queue", "list"]).await?)
+    }
+
+    /// callers in `queue`
+    pub async fn queue_members(&self, queue: &str) -> Result<Vec<QueueMemberRow>> {
+        parse_list(
+            &self
+                .config(&["queue", "list", "members", token(queue)?])
+                .await?,
+        )
+    }
+
+    /// agents of `queue`, only those with `status` when given
+    pub async fn queue_agents(
+        &self,
+        queue: &str,
+        status: Option<AgentStatus>,
+    ) -> Result<Vec<AgentRow>> {
+        let status = status
+            .map(|status| quote(status.as_str()))
+            .transpose()?
+            .unwrap_or_default();
+        parse_list(
+            &self
+                .config(&["queue", "list", "agents", token(queue)?, &status])
+                .await?,
+        )
+    }
+
+    pub async fn queue_tiers(&self, queue: &str) -> Result<Vec<TierRow>> {
+        parse_list(
+            &self
+                .config(&["queue", "list", "tiers", token(queue)?])
+                .await?,
+        )
+    }
+
+    pub async fn queue_count(&self, queue: &str, what: QueueCount) -> Result<u64> {
+        let what = match what {
+            QueueCount::Agents => "agents",
+            QueueCount::Members => "members",
+            QueueCount::Tiers => "tiers",
+        };
+        let count = self
+            .config(&["queue", "count", what, token(queue)?])
+            .await?;
+        Ok(count.trim().parse()?)
+    }
+
+    /// `callcenter_config <args>`, empty arguments are left out
+    async fn config(&self, args: &[&str]) -> Result<String> {
+        let mut line = "callcenter_config".to_string();
+        for arg in args.iter().filter(|arg| !arg.is_empty()) {
+            line.push(' ');
+            line.push_str(arg);
+        }
+        api_result(self.conn.execute(&Command::api(line)).await?)
+    }
+}
+
+/// a `callcenter::info` event, by `CC-Action`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CallcenterEvent {
+    AgentStatusChange {
+        agent: String,
+        status: AgentStatus,
+    },
+    AgentStateChange {
+        agent: String,
+        state: AgentState,
+    },
+    /// a caller is offered to an agent
+    AgentOffering {
+        queue: String,
+        agent: String,
+        member_uuid: String,
+        member_session_uuid: String,
+    },
+    /// an agent answered and is bridged to the caller
+    BridgeAgentStart {
+        queue: String,
+        agent: String,
+        agent_uuid: String,
+        member_uuid: String,
+        member_session_uuid: String,
+    },
+    BridgeAgentEnd {
+        queue: String,
+        agent: String,
+        agent_uuid: String,
+        member_uuid: String,
+        member_session_uuid: String,
+        hangup_cause: Option<HangupCause>,
+    },
+    /// the agent could not be reached
+    BridgeAgentFail {
+        queue: String,
+        agent: String,
+        member_uuid: String,
+        member_session_uuid: String,
+        hangup_cause: Option<HangupCause>,
+    },
+    /// a caller entered the queue
+    MemberQueueStart {
+        queue: String,
+        member_uuid: String,
+        member_session_uuid: String,
+        caller_id_name: String,
+        caller_id_number: String,
+    },
+    /// a caller left the queue, `cause` is `Terminated` or `Cancel`
+    MemberQueueEnd {
+        queue: String,
+        member_uuid: String,
+        member_session_uuid: String,
+        cause: String,
+        cancel_reason: Option<String>,
+    },
+    MembersCount {
+        queue: String,
+        count: u64,
+    },
+    /// an action not modelled here
+    Other(String),
+}
+
+impl CallcenterEvent {
+    /// `None` for events that are not `callcenter::info`
+    pub fn from_event(evt: &Event) -> Option<Self> {
+        let Event::Custom(data) = evt else {
+            return None;
+        };
+        if data.get_subclass().as_deref() != Some(SUBCLASS) {
+            return None;
+        }
+        let get = |key: &str| data.get_body_by_key(key).unwrap_or_default();
+        let hangup_cause = || {
+            data.get_body_by_key("CC-Hangup-Cause")
+                .and_then(|cause| cause.parse().ok())
+        };
+        let action = data.get_body_by_key("CC-Action")?;
+        Some(match action.as_str() {
+            "agent-status-change" => Self::AgentStatusChange {
+                agent: get("CC-Agent"),
+                status: get("CC-Agent-Status").parse().ok()?,
+            },
+            "agent-state-change" => Self::AgentStateChange {
+                agent: get("CC-Agent"),
+                state: get("CC-Agent-State").parse().ok()?,
+            },
+            "agent-offering" => Self::AgentOffering {
+                queue: get("CC-Queue"),
+                agent: get("CC-Agent"),
+                member_uuid: get("CC-Member-UUID"),
+                member_session_uuid: get("CC-Member-Session-UUID"),
+            },
+            "bridge-agent-start" => Self::BridgeAgentStart {
+                queue: get("CC-Queue"),
+                agent: get("CC-Agent"),
+                agent_uuid: get("CC-Agent-UUID"),
+                member_uuid: get("CC-Member-UUID"),
+                member_session_uuid: get("CC-Member-Session-UUID"),
+            },
+            "bridge-agent-end" => Self::BridgeAgentEnd {
+                queue: get("CC-Queue"),
+                agent: get("CC-Agent"),
+                agent_uuid: get("CC-Agent-UUID"),
+                member_uuid: get("CC-Member-UUID"),
+                member_session_uuid: get("CC-Member-Session-UUID"),
+                hangup_cause: hangup_cause(),
+            },
+            "bridge-agent-fail" => Self::BridgeAgentFail {
+                queue: get("CC-Queue"),
+                agent: get("CC-Agent"),
+                member_uuid: get("CC-Member-UUID"),
+                member_session_uuid: get("CC-Member-Session-UUID"),
+                hangup_cause: hangup_cause(),
+            },
+            "member-queue-start" => Self::MemberQueueStart {
+                queue: get("CC-Queue"),
+                member_uuid: get("CC-Member-UUID"),
+                member_session_uuid: get("CC-Member-Session-UUID"),
+                caller_id_name: get("CC-Member-CID-Name"),
+                caller_id_number: get("CC-Member-CID-Number"),
+            },
+            "member-queue-end" => Self::MemberQueueEnd {
+                queue: get("CC-Queue"),
+                member_uuid: get("CC-Member-UUID"),
+                member_session_uuid: get("CC-Member-Session-UUID"),
+                cause: get("CC-Cause"),
+                cancel_reason: data.get_body_by_key("CC-Cancel-Reason"),
+            },
+            "members-count" => Self::MembersCount {
+                queue: get("CC-Queue"),
+                count: get("CC-Count").trim().parse().ok()?,
+            },
+            _ => Self::Other(action),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tests::{json_event, mock_freeswitch_steps},
+        Esl,
+    };
+
+    /// an `api/response` with `body`
+    fn api(body: &str) -> &'static str {
+        let reply = format!(
+            "Content-Type: api/response\nContent-Length: {}\n\n{}",
+            body.len(),
+            body
+        );
+        Box::leak(reply.into_boxed_str())
+    }
+
+    #[tokio::test]
+    async fn command_lines() {
+        let ok = api("+OK\n");
+        let (addr, mut commands) = mock_freeswitch_steps(vec![
+            (1, ok),
+            (1, ok),
+            (1, ok),
+            (1, ok),
+            (1, ok),
+            (1, ok),
+            (1, api("Available (On Demand)\n")),
+            (1, api("3\n")),
+            (1, api("name|status\n1000@default|Available\n+OK\n")),
+        ])
+        .await;
+        let conn = Esl::inbound(addr, "ClueCon").await.unwrap();
+        let cc = conn.callcenter();
+        cc.agent_add("1000@default", AgentType::UuidStandby)
+            .await
+            .unwrap();
+        cc.agent_set_status("1000@default", AgentStatus::OnBreak)
+            .await
+            .unwrap();
+        cc.agent_set(
+            "1000@default",
+            AgentSetting::Contact("[call_timeout=10]user/1000".to_string()),
+        )
+        .await
+        .unwrap();
+        cc.tier_add("support@default", "1000@default", 1, 2)
+            .await
+            .unwrap();
+        cc.tier_set_state("support@default", "1000@default", TierState::ActiveInbound)
+            .await
+            .unwrap();
+        cc.queue_load("support@default").await.unwrap();
+        assert_eq!(
+            cc.agent_status("1000@default").await.unwrap(),
+            AgentStatus::AvailableOnDemand
+        );
+        assert_eq!(
+            cc.queue_count("support@default", QueueCount::Members)
+                .await
+                .unwrap(),
+            3
+        );
+        let agents = cc
+            .queue_agents("support@default", Some(AgentStatus::Available))
+            .await
+            .unwrap();
+        assert_eq!(agents[0].status, AgentStatus::Available);
+
+        let mut lines = Vec::new();
+        while let Ok(line) = commands.try_recv() {
+            lines.push(line);
+        }
+        assert_eq!(
+            lines,
+            [
+                "api callcenter_config agent add 1000@default uuid-standby",
+                "api callcenter_config agent set status 1000@default 'On Break'",
+                "api callcenter_config agent set contact 1000@default [call_timeout=10]user/1000",
+                "api callcenter_config tier add support@default 1000@default 1 2",
+                "api callcenter_config tier set state support@default 1000@default 'Active Inbound'",
+                "api callcenter_config queue load support@default",
+                "api callcenter_config agent get status 1000@default",
+                "api callcenter_config queue count members support@default",
+                "api callcenter_config queue list agents support@default Available",
+            ]
+        );
+        assert!(matches!(
+            cc.tier_set_level("support queue", "1000@default", 1).await,
+            Err(crate::error::EslError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn parse_lists() {
+        let agents: Vec<AgentRow> = parse_list(
+            "name|instance_id|uuid|type|contact|status|state|max_no_answer|wrap_up_time|talk_time\n\
+             1000@default|single_box||callback|[call_timeout=10]user/1000|Available (On Demand)|Waiting|3|10|0\n\
+             +OK\n",
+        )
+        .unwrap();
+        assert_eq!(agents.len(), 1);
+        assert_eq!(agents[0].agent_type, AgentType::Callback);
+        assert_eq!(agents[0].status, AgentStatus::AvailableOnDemand);
+        assert_eq!(agents[0].state, AgentState::Waiting);
+        assert_eq!(agents[0].max_no_answer, Some(3));
+
+        let tiers: Vec<TierRow> = parse_list(
+            "queue|agent|state|level|position\nsupport@default|1000@default|No Answer|1|2\n+OK\n",
+        )
+        .unwrap();
+        assert_eq!(tiers[0].state, TierState::NoAnswer);
+        assert_eq!(tiers[0].position, Some(2));
+        let tiers: Vec<TierRow> = parse_list(
+            "queue|agent|state|level|position\nsupport@default|1000@default|Active Inbound|1|1\n+OK\n",
+        )
+        .unwrap();
+        assert_eq!(tiers[0].state, TierState::ActiveInbound);
+
+        let members: Vec<QueueMemberRow> = parse_list("queue|uuid|state\n+OK\n").unwrap();
+        assert!(members.is_empty());
+    }
+
+    #[test]
+    fn typed_events() {
+        let status = json_event(serde_json::json!({
+            "Event-Name": "CUSTOM",
+            "Event-Subclass": "callcenter::info",
+            "CC-Action": "agent-status-change",
+            "CC-Agent": "1000@default",
+            "CC-Agent-Status": "On Break",
+        }));
+        assert_eq!(
+            CallcenterEvent::from_event(&status),
+            Some(CallcenterEvent::AgentStatusChange {
+                agent: "1000@default".to_string(),
+                status: AgentStatus::OnBreak,
+            })
+        );
+        let end = json_event(serde_json::json!({
+            "Event-Name": "CUSTOM",
+            "Event-Subclass": "callcenter::info",
+            "CC-Action": "bridge-agent-end",
+            "CC-Queue": "support@default",
+            "CC-Agent": "1000@default",
+            "CC-Hangup-Cause": "NORMAL_CLEARING",
+        }));
+        assert!(matches!(
+            CallcenterEvent::from_event(&end),
+            Some(CallcenterEvent::BridgeAgentEnd {
+                hangup_cause: Some(HangupCause::NormalClearing),
+                ..
+            })
+        ));
+        let other = json_event(serde_json::json!({
+            "Event-Name": "CUSTOM",
+            "Event-Subclass": "conference::maniacal",
+            "CC-Action": "agent-offering",
+        }));
+        assert_eq!(CallcenterEvent::from_event(&other), None);
+    }
+}
@@ -0,0 +1,417 @@
+//! call detail records from CHANNEL_HANGUP_COMPLETE
+//!
+//! a [`Cdr`] takes the timestamps, caller and callee, codecs and hangup cause
+//! of one leg, plus whichever channel variables are asked for. [`CdrSink`]
+//! writes them out one by one, as JSON lines ([`JsonLines`]) or CSV
+//! ([`Csv`]).
+//!
+//! ```no_run
+//! # async fn demo(conn: esl_rs::conn::Conn) -> esl_rs::error::Result<()> {
+//! use esl_rs::cdr::{Cdr, CdrSink, JsonLines};
+//! use esl_rs::event::Event;
+//!
+//! let mut sink = JsonLines::new(std::fs::File::create("cdr.jsonl")?);
+//! let mut events = conn.events();
+//! while let Ok(evt) = events.recv().await {
+//!     if let Event::ChannelHangupComplete(_) = evt {
+//!         sink.write(&Cdr::from_event(&evt, &["queue"])?)?;
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Serialize, Serializer};
+
+use crate::{
+    error::{EslError, Result},
+    event::Event,
+    hangup::HangupCause,
+};
+
+/// how a call ended, from the answer time and the hangup cause
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Disposition {
+    Answered,
+    NoAnswer,
+    Busy,
+    /// the caller gave up before an answer
+    Cancelled,
+    Failed,
+}
+
+impl Disposition {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Answered => "ANSWERED",
+            Self::NoAnswer => "NO_ANSWER",
+            Self::Busy => "BUSY",
+            Self::Cancelled => "CANCELLED",
+            Self::Failed => "FAILED",
+        }
+    }
+}
+
+/// one call detail record, see the [module docs](self)
+///
+/// timestamps serialize as microseconds since the epoch and durations as
+/// whole seconds, like freeswitch's own `*_epoch` and `*sec` variables
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Cdr {
+    pub uuid: String,
+    /// `Channel-Call-UUID`, shared by the legs of a bridged call
+    pub call_uuid: Option<String>,
+    pub direction: Option<String>,
+    pub caller_id_name: Option<String>,
+    pub caller_id_number: Option<String>,
+    pub destination_number: Option<String>,
+    pub callee_id_name: Option<String>,
+    pub callee_id_number: Option<String>,
+    pub context: Option<String>,
+    #[serde(serialize_with = "epoch_micros")]
+    pub start: Option<SystemTime>,
+    #[serde(serialize_with = "epoch_micros")]
+    pub progress: Option<SystemTime>,
+    #[serde(serialize_with = "epoch_micros")]
+    pub answer: Option<SystemTime>,
+    #[serde(serialize_with = "epoch_micros")]
+    pub end: Option<SystemTime>,
+    /// from creation until hangup
+    #[serde(serialize_with = "seconds")]
+    pub duration: Duration,
+    /// from answer until hangup, zero when unanswered
+    #[serde(serialize_with = "seconds")]
+    pub billsec: Duration,
+    /// from creation until answer, or hangup when unanswered
+    #[serde(serialize_with = "seconds")]
+    pub waitsec: Duration,
+    /// from creation until the first 180/183
+    #[serde(serialize_with = "seconds")]
+    pub progresssec: Duration,
+    #[serde(serialize_with = "seconds")]
+    pub holdsec: Duration,
+    #[serde(serialize_with = "cause")]
+    pub hangup_cause: Option<HangupCause>,
+    pub disposition: Disposition,
+    pub read_codec: Option<String>,
+    pub write_codec: Option<String>,
+    /// the variables asked for, missing ones are left out
+    pub vars: BTreeMap<String, String>,
+}
+
+impl Cdr {
+    /// build a record from CHANNEL_HANGUP_COMPLETE, keeping the channel
+    /// variables named in `vars`
+    pub fn from_event(evt: &Event, vars: &[&str]) -> Result<Self> {
+        if !matches!(evt, Event::ChannelHangupComplete(_)) {
+            return Err(EslError::InvalidArgument(format!(
+                "cdr from {:?}, expected CHANNEL_HANGUP_COMPLETE",
+                evt.get_event_name()
+            )));
+        }
+        let uuid = evt
+            .get_body_by_key("Unique-ID")
+            .ok_or_else(|| EslError::DeserializeError("cdr without Unique-ID".to_string()))?;
+        let hangup_cause = evt.hangup_cause();
+        let answer = evt.answered_time();
+        let disposition = match (&answer, &hangup_cause) {
+            (Some(_), _) => Disposition::Answered,
+            (None, Some(HangupCause::UserBusy)) => Disposition::Busy,
+            (None, Some(HangupCause::NoAnswer | HangupCause::NoUserResponse)) => {
+                Disposition::NoAnswer
+            }
+            (None, Some(HangupCause::OriginatorCancel)) => Disposition::Cancelled,
+            (None, _) => Disposition::Failed,
+        };
+        let get = |key: &str| evt.get_body_by_key(key).filter(|v| !v.is_empty());
+        Ok(Self {
+            call_uuid: evt.get_channel_call_uuid(),
+            direction: get("Call-Direction"),
+            caller_id_name: get("Caller-Caller-ID-Name"),
+            caller_id_number: get("Caller-Caller-ID-Number"),
+            destination_number: get("Caller-Destination-Number"),
+            callee_id_name: get("Caller-Callee-ID-Name"),
+            callee_id_number: get("Caller-Callee-ID-Number"),
+            context: get("Caller-Context"),
+            start: evt.created_time(),
+            progress: match (evt.progress_time(), evt.progress_media_time()) {
+                (Some(a), Some(b)) => Some(a.min(b)),
+                (a, b) => a.or(b),
+            },
+            answer,
+            end: evt.hangup_time(),
+            duration: evt.total_duration().unwrap_or_default(),
+            billsec: evt.talk_time().unwrap_or_default(),
+            waitsec: evt.ring_time().unwrap_or_default(),
+            progresssec: evt.progress_delay().unwrap_or_default(),
+            holdsec: evt.hold_time().unwrap_or_default(),
+            hangup_cause,
+            disposition,
+            read_codec: get("Channel-Read-Codec-Name"),
+            write_codec: get("Channel-Write-Codec-Name"),
+            vars: vars
+                .iter()
+                .filter_map(|name| Some((name.to_string(), evt.get_var(name)?)))
+                .collect(),
+            uuid,
+        })
+    }
+}
+
+fn epoch_micros<S: Serializer>(
+    time: &Option<SystemTime>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    time.and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|since| since.as_micros() as u64)
+        .serialize(serializer)
+}
+
+fn seconds<S: Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_u64(duration.as_secs())
+}
+
+fn cause<S: Serializer>(
+    cause: &Option<HangupCause>,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    cause
+        .as_ref()
+        .map(HangupCause::as_str)
+        .serialize(serializer)
+}
+
+/// somewhere to put records as they come
+pub trait CdrSink {
+    fn write(&mut self, cdr: &Cdr) -> Result<()>;
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// one JSON object per line
+#[derive(Debug)]
+pub struct JsonLines<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> JsonLines<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> CdrSink for JsonLines<W> {
+    fn write(&mut self, cdr: &Cdr) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, cdr)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+/// the fixed columns of [`Csv`], the variables follow
+const CSV_COLUMNS: [&str; 23] = [
+    "uuid",
+    "call_uuid",
+    "direction",
+    "caller_id_name",
+    "caller_id_number",
+    "destination_number",
+    "callee_id_name",
+    "callee_id_number",
+    "context",
+    "start",
+    "progress",
+    "answer",
+    "end",
+    "duration",
+    "billsec",
+    "waitsec",
+    "progresssec",
+    "holdsec",
+    "hangup_cause",
+    "hangup_cause_code",
+    "disposition",
+    "read_codec",
+    "write_codec",
+];
+
+/// comma separated values with a header line, quoted as in RFC 4180
+///
+/// the variable columns are fixed up front so every row lines up
+#[derive(Debug)]
+pub struct Csv<W: Write> {
+    writer: W,
+    vars: Vec<String>,
+    header: bool,
+}
+
+impl<W: Write> Csv<W> {
+    /// `vars` are the variable columns, after the fixed ones
+    pub fn new(writer: W, vars: &[&str]) -> Self {
+        Self {
+            writer,
+            vars: vars.iter().map(|var| var.to_string()).collect(),
+            header: false,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    fn write_row<'a>(&mut self, fields: impl Iterator<Item = &'a str>) -> Result<()> {
+        let row: Vec<_> = fields.map(csv_field).collect();
+        self.writer.write_all(row.join(",").as_bytes())?;
+        self.writer.write_all(b"\r\n")?;
+        Ok(())
+    }
+}
+
+impl<W: Write> CdrSink for Csv<W> {
+    fn write(&mut self, cdr: &Cdr) -> Result<()> {
+        if !self.header {
+            let vars = std::mem::take(&mut self.vars);
+            self.write_row(
+                CSV_COLUMNS
+                    .into_iter()
+                    .chain(vars.iter().map(String::as_str)),
+            )?;
+            self.vars = vars;
+            self.header = true;
+        }
+        let text = |value: &Option<String>| value.clone().unwrap_or_default();
+        let time = |time: &Option<SystemTime>| {
+            time.and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|since| since.as_micros().to_string())
+                .unwrap_or_default()
+        };
+        let mut fields = vec![
+            cdr.uuid.clone(),
+            text(&cdr.call_uuid),
+            text(&cdr.direction),
+            text(&cdr.caller_id_name),
+            text(&cdr.caller_id_number),
+            text(&cdr.destination_number),
+            text(&cdr.callee_id_name),
+            text(&cdr.callee_id_number),
+            text(&cdr.context),
+            time(&cdr.start),
+            time(&cdr.progress),
+            time(&cdr.answer),
+            time(&cdr.end),
+            cdr.duration.as_secs().to_string(),
+            cdr.billsec.as_secs().to_string(),
+            cdr.waitsec.as_secs().to_string(),
+            cdr.progresssec.as_secs().to_string(),
+            cdr.holdsec.as_secs().to_string(),
+            cdr.hangup_cause
+                .as_ref()
+                .map(|cause| cause.to_string())
+                .unwrap_or_default(),
+            cdr.hangup_cause
+                .as_ref()
+                .and_then(HangupCause::code)
+                .map(|code| code.to_string())
+                .unwrap_or_default(),
+            cdr.disposition.as_str().to_string(),
+            text(&cdr.read_codec),
+            text(&cdr.write_codec),
+        ];
+        fields.extend(
+            self.vars
+                .iter()
+                .map(|var| cdr.vars.get(var).cloned().unwrap_or_default()),
+        );
+        self.write_row(fields.iter().map(String::as_str))
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::json_event;
+
+    fn hangup_complete() -> Event {
+        let body = serde_json::json!({
+            "Event-Name": "CHANNEL_HANGUP_COMPLETE",
+            "Unique-ID": "7f4d",
+            "Call-Direction": "inbound",
+            "Caller-Caller-ID-Name": "Doe, John",
+            "Caller-Caller-ID-Number": "1000",
+            "Caller-Destination-Number": "2000",
+            "Caller-Channel-Created-Time": "1700000000000000",
+            "Caller-Channel-Progress-Time": "1700000001000000",
+            "Caller-Channel-Answered-Time": "1700000004000000",
+            "Caller-Channel-Hangup-Time": "1700000064500000",
+            "Hangup-Cause": "NORMAL_CLEARING",
+            "Channel-Read-Codec-Name": "PCMU",
+            "variable_queue": "sales",
+        });
+        json_event(body)
+    }
+
+    #[test]
+    fn build_and_write() {
+        let cdr = Cdr::from_event(&hangup_complete(), &["queue", "missing"]).unwrap();
+        assert_eq!(cdr.disposition, Disposition::Answered);
+        assert_eq!(cdr.billsec, Duration::from_millis(60_500));
+        assert_eq!(cdr.waitsec, Duration::from_secs(4));
+        assert_eq!(cdr.progresssec, Duration::from_secs(1));
+        assert_eq!(cdr.vars.len(), 1);
+
+        let mut jsonl = JsonLines::new(Vec::new());
+        jsonl.write(&cdr).unwrap();
+        let line = String::from_utf8(jsonl.into_inner()).unwrap();
+        let value: serde_json::Value = serde_json::from_str(line.trim_end()).unwrap();
+        assert_eq!(value["billsec"], 60);
+        assert_eq!(value["answer"], 1_700_000_004_000_000u64);
+        assert_eq!(value["hangup_cause"], "NORMAL_CLEARING");
+        assert_eq!(value["vars"]["queue"], "sales");
+
+        let mut csv = Csv::new(Vec::new(), &["queue"]);
+        csv.write(&cdr).unwrap();
+        csv.write(&cdr).unwrap();
+        let text = String::from_utf8(csv.into_inner()).unwrap();
+        let lines: Vec<_> = text.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("uuid,call_uuid,") && lines[0].ends_with(",queue"));
+        assert!(lines[1].contains(",\"Doe, John\",1000,2000,"));
+        assert!(lines[1].ends_with(",NORMAL_CLEARING,16,ANSWERED,PCMU,,sales"));
+
+        let event =
+            json_event(serde_json::json!({"Event-Name": "CHANNEL_HANGUP", "Unique-ID": "7f4d"}));
+        assert!(Cdr::from_event(&event, &[]).is_err());
+    }
+}
@@ -0,0 +1,358 @@
+//! `uuid_*` commands on one channel
+//!
+//! ```no_run
+//! # async fn demo(conn: esl_rs::conn::Conn) -> esl_rs::error::Result<()> {
+//! let channel = conn.channel("4f3a0b3e-5a5c-4c2e-9d3c-1c3b2a1f0e9d")?;
+//! channel.set_var("hold_music", "local_stream://moh").await?;
+//! channel.hold().await?;
+//! channel.bridge("0c5f8c2a-8a52-4b5e-b3a5-7a4c1f2e3d4b").await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::time::Duration;
+
+use crate::{
+    command::Command,
+    conn::Conn,
+    error::{EslError, Result},
+    event::EventData,
+    hangup::HangupCause,
+    reply::{api_result, reply_result},
+    show::parse_dump,
+};
+
+/// which legs a command applies to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Legs {
+    #[default]
+    ALeg,
+    BLeg,
+    Both,
+}
+
+/// a channel on a [`Conn`], see [`Conn::channel`]
+#[derive(Debug, Clone)]
+pub struct Channel {
+    conn: Conn,
+    uuid: String,
+}
+
+impl Conn {
+    /// control the channel `uuid`, fails when it is not a single token
+    pub fn channel(&self, uuid: impl Into<String>) -> Result<Channel> {
+        let uuid = uuid.into();
+        token(&uuid)?;
+        Ok(Channel {
+            conn: self.clone(),
+            uuid,
+        })
+    }
+}
+
+impl Channel {
+    pub fn uuid(&self) -> &str {
+        &self.uuid
+    }
+
+    pub(crate) fn conn(&self) -> &Conn {
+        &self.conn
+    }
+
+    /// `sendmsg` `execute`, queue the dialplan application `app` on the
+    /// channel. it runs after the applications queued before it
+    pub async fn execute(&self, app: &str, args: &str) -> Result<()> {
+        self.execute_tagged(app, args, None).await
+    }
+
+    /// [`Channel::execute`] with an `Event-UUID`, which comes back as
+    /// `Application-UUID` on CHANNEL_EXECUTE_COMPLETE
+    pub(crate) async fn execute_tagged(
+        &self,
+        app: &str,
+        args: &str,
+        event_uuid: Option<&str>,
+    ) -> Result<()> {
+        let mut command = Command::sendmsg(Some(&self.uuid))
+            .header("call-command", "execute")
+            .header("execute-app-name", token(app)?);
+        if !args.is_empty() {
+            command = command.header("execute-app-arg", args);
+        }
+        if let Some(event_uuid) = event_uuid {
+            command = command.header("Event-UUID", event_uuid);
+        }
+        reply_result(self.conn.execute(&command).await?)?;
+        Ok(())
+    }
+
+    /// `uuid_exists`
+    pub async fn exists(&self) -> Result<bool> {
+        Ok(self.uuid_api("uuid_exists", &[]).await?.trim() == "true")
+    }
+
+    /// `uuid_dump`, the channel's headers and variables as an event
+    pub async fn dump(&self) -> Result<EventData> {
+        parse_dump(&self.uuid_api("uuid_dump", &["json"]).await?)
+    }
+
+    /// `uuid_answer`
+    pub async fn answer(&self) -> Result<()> {
+        self.uuid_api("uuid_answer", &[]).await?;
+        Ok(())
+    }
+
+    /// `uuid_bridge`, connect this channel to `other`
+    pub async fn bridge(&self, other: &str) -> Result<()> {
+        self.uuid_api("uuid_bridge", &[token(other)?]).await?;
+        Ok(())
+    }
+
+    /// `uuid_kill`, hang up with `cause`, `NORMAL_CLEARING` when `None`
+    pub async fn kill(&self, cause: Option<HangupCause>) -> Result<()> {
+        let cause = cause.map(|cause| cause.to_string()).unwrap_or_default();
+        self.uuid_api("uuid_kill", &[&cause]).await?;
+        Ok(())
+    }
+
+    /// `uuid_transfer`, send `legs` to `extension`, dialplan and context
+    /// default to the channel's own. a context without a dialplan goes
+    /// through `XML`
+    pub async fn transfer(
+        &self,
+        extension: &str,
+        dialplan: Option<&str>,
+        context: Option<&str>,
+        legs: Legs,
+    ) -> Result<()> {
+        let args = transfer_args(extension, dialplan, context, legs)?;
+        self.uuid_api("uuid_transfer", &args).await?;
+        Ok(())
+    }
+
+    /// `uuid_hold`, put the channel on hold
+    pub async fn hold(&self) -> Result<()> {
+        self.api(&format!("uuid_hold {}", self.uuid)).await?;
+        Ok(())
+    }
+
+    /// `uuid_hold off`
+    pub async fn unhold(&self) -> Result<()> {
+        self.api(&format!("uuid_hold off {}", self.uuid)).await?;
+        Ok(())
+    }
+
+    /// `uuid_hold toggle`
+    pub async fn toggle_hold(&self) -> Result<()> {
+        self.api(&format!("uuid_hold toggle {}", self.uuid)).await?;
+        Ok(())
+    }
+
+    /// `uuid_break`, stop the current playback, `all` also flushes the queue
+    pub async fn break_media(&self, all: bool) -> Result<()> {
+        self.uuid_api("uuid_break", &[if all { "all" } else { "" }])
+            .await?;
+        Ok(())
+    }
+
+    /// `uuid_broadcast`, play `path` (or run `app::args`) on `legs`
+    pub async fn broadcast(&self, path: &str, legs: Legs) -> Result<()> {
+        let legs = match legs {
+            Legs::ALeg => "aleg",
+            Legs::BLeg => "bleg",
+            Legs::Both => "both",
+        };
+        self.uuid_api("uuid_broadcast", &[&quote(path)?, legs])
+            .await?;
+        Ok(())
+    }
+
+    /// `uuid_record start`, record in the background, `limit` caps the
+    /// recording length. see [`Channel::record`] to wait for the recording
+    pub async fn start_record(&self, path: &str, limit: Option<Duration>) -> Result<()> {
+        let limit = limit
+            .map(|limit| limit.as_secs().to_string())
+            .unwrap_or_default();
+        self.uuid_api("uuid_record", &["start", &quote(path)?, &limit])
+            .await?;
+        Ok(())
+    }
+
+    /// `uuid_record stop`, a `path` of `all` stops every recording of the channel
+    pub async fn stop_record(&self, path: &str) -> Result<()> {
+        self.uuid_api("uuid_record", &["stop", &quote(path)?])
+            .await?;
+        Ok(())
+    }
+
+    /// `uuid_setvar`, the value may contain spaces
+    pub async fn set_var(&self, name: &str, value: impl ToString) -> Result<()> {
+        let value = value.to_string();
+        if value.is_empty() {
+            return self.unset_var(name).await;
+        }
+        self.uuid_api("uuid_setvar", &[token(name)?, &value])
+            .await?;
+        Ok(())
+    }
+
+    /// `uuid_setvar` without a value
+    pub async fn unset_var(&self, name: &str) -> Result<()> {
+        self.uuid_api("uuid_setvar", &[token(name)?]).await?;
+        Ok(())
+    }
+
+    /// `uuid_getvar`, `None` when the variable is not set
+    pub async fn get_var(&self, name: &str) -> Result<Option<String>> {
+        let value = self.uuid_api("uuid_getvar", &[token(name)?]).await?;
+        let value = value.trim_end_matches(['\r', '\n']);
+        Ok((value != "_undef_" && !value.is_empty()).then(|| value.to_string()))
+    }
+
+    /// `uuid_park`
+    pub async fn park(&self) -> Result<()> {
+        self.uuid_api("uuid_park", &[]).await?;
+        Ok(())
+    }
+
+    /// `uuid_send_dtmf`, `tone` is the duration of each digit
+    pub async fn send_dtmf(&self, digits: &str, tone: Option<Duration>) -> Result<()> {
+        let digits = token(digits)?;
+        if let Some(c) = digits
+            .chars()
+            .find(|c| !c.is_ascii_alphanumeric() && !matches!(c, '*' | '#' | 'w' | 'W'))
+        {
+            return Err(EslError::InvalidArgument(format!("dtmf digit {:?}", c)));
+        }
+        let digits = match tone {
+            Some(tone) => format!("{}@{}", digits, tone.as_millis()),
+            None => digits.to_string(),
+        };
+        self.uuid_api("uuid_send_dtmf", &[&digits]).await?;
+        Ok(())
+    }
+
+    /// `<command> <uuid> <args>`, empty arguments are left out
+    async fn uuid_api(&self, command: &str, args: &[&str]) -> Result<String> {
+        let mut line = format!("{} {}", command, self.uuid);
+        for arg in args.iter().filter(|arg| !arg.is_empty()) {
+            line.push(' ');
+            line.push_str(arg);
+        }
+        self.api(&line).await
+    }
+
+    async fn api(&self, command: &str) -> Result<String> {
+        api_result(self.conn.execute(&Command::api(command)).await?)
+    }
+}
+
+/// a single word argument such as a uuid or a variable name
+pub(crate) fn token(value: &str) -> Result<&str> {
+    if value.is_empty() || value.contains(char::is_whitespace) {
+        return Err(EslError::InvalidArgument(format!(
+            "expected a single word, got {:?}",
+            value
+        )));
+    }
+    Ok(value)
+}
+
+fn opt_token(value: Option<&str>) -> Result<&str> {
+    value.map_or(Ok(""), token)
+}
+
+/// arguments are positional, the context can only follow a dialplan
+fn transfer_args<'a>(
+    extension: &'a str,
+    dialplan: Option<&'a str>,
+    context: Option<&'a str>,
+    legs: Legs,
+) -> Result<[&'a str; 4]> {
+    let legs = match legs {
+        Legs::ALeg => "",
+        Legs::BLeg => "-bleg",
+        Legs::Both => "-both",
+    };
+    let dialplan = match (dialplan, context) {
+        (None, Some(_)) => Some("XML"),
+        _ => dialplan,
+    };
+    Ok([
+        legs,
+        token(extension)?,
+        opt_token(dialplan)?,
+        opt_token(context)?,
+    ])
+}
+
+/// arguments such as paths may contain spaces, freeswitch splits them outside
+/// `'` quotes
+pub(crate) fn quote(value: &str) -> Result<String> {
+    if value.is_empty() || value.contains('\'') {
+        return Err(EslError::InvalidArgument(format!(
+            "expected a non-empty argument without quotes, got {:?}",
+            value
+        )));
+    }
+    if value.contains(char::is_whitespace) {
+        Ok(format!("'{}'", value))
+    } else {
+        Ok(value.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{tests::mock_freeswitch, Esl};
+
+    #[tokio::test]
+    async fn channel_commands() {
+        let addr = mock_freeswitch(
+            "Content-Type: api/response\nContent-Length: 4\n\ntrue\
+             Content-Type: api/response\nContent-Length: 7\n\n_undef_\
+             Content-Type: api/response\nContent-Length: 4\n\n+OK\n\
+             Content-Type: api/response\nContent-Length: 23\n\n-ERR No such channel!\n\n",
+            4,
+        )
+        .await;
+        let conn = Esl::inbound(addr, "ClueCon").await.unwrap();
+        let channel = conn.channel("7f4d").unwrap();
+        // the mock answers once all four commands are written
+        let (exists, var, set, park) = tokio::join!(
+            channel.exists(),
+            channel.get_var("missing"),
+            channel.set_var("greeting", "hello world"),
+            channel.park(),
+        );
+        assert!(exists.unwrap());
+        assert_eq!(var.unwrap(), None);
+        set.unwrap();
+        assert_eq!(park, Err(EslError::NoSuchChannel));
+    }
+
+    #[test]
+    fn reject_arguments() {
+        assert!(token("a b").is_err());
+        assert!(token("").is_err());
+        assert_eq!(quote("/tmp/a b.wav").unwrap(), "'/tmp/a b.wav'");
+        assert!(quote("it's.wav").is_err());
+    }
+
+    #[test]
+    fn transfer_defaults() {
+        assert_eq!(
+            transfer_args("1000", None, Some("public"), Legs::ALeg).unwrap(),
+            ["", "1000", "XML", "public"]
+        );
+        assert_eq!(
+            transfer_args("1000", Some("inline"), None, Legs::Both).unwrap(),
+            ["-both", "1000", "inline", ""]
+        );
+        assert_eq!(
+            transfer_args("1000", None, None, Legs::BLeg).unwrap(),
+            ["-bleg", "1000", "", ""]
+        );
+    }
+}
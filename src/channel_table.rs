@@ -0,0 +1,585 @@
+//! live view of the channels on a switch
+//!
+//! a [`ChannelTable`] is seeded from `show channels as json` and then kept
+//! current from channel events. it is opt-in: nothing is tracked until
+//! [`ChannelTable::attach`] is called, or [`ChannelTable::attach_with`] to
+//! reconnect and resync on its own.
+//!
+//! ```no_run
+//! # async fn demo(conn: esl_rs::conn::Conn) -> esl_rs::error::Result<()> {
+//! use esl_rs::channel_table::ChannelTable;
+//!
+//! let table = ChannelTable::new();
+//! table.attach(&conn).await?;
+//! let mut changes = table.changes();
+//! while let Ok(change) = changes.recv().await {
+//!     println!("{:?}, {} channels", change, table.len().await);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use tokio::sync::{broadcast, Mutex};
+use tracing::{debug, warn};
+
+use crate::{
+    command::{Command, EventFormat},
+    conn::Conn,
+    error::Result,
+    event::{Event, EventData},
+    hangup::HangupCause,
+    reply::reply_result,
+    show::ChannelRow,
+};
+
+/// events that change the table
+const CHANNEL_EVENTS: [&str; 7] = [
+    "CHANNEL_CREATE",
+    "CHANNEL_ANSWER",
+    "CHANNEL_BRIDGE",
+    "CHANNEL_UNBRIDGE",
+    "CHANNEL_CALLSTATE",
+    "CHANNEL_HANGUP",
+    "CHANNEL_DESTROY",
+];
+
+/// wait between attempts of [`ChannelTable::attach_with`] to reconnect
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// what is known about one channel
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChannelState {
+    pub uuid: String,
+    pub name: String,
+    pub direction: String,
+    /// `Channel-State`, e.g. `CS_EXECUTE`
+    pub state: String,
+    /// `Channel-Call-State`, e.g. `RINGING` or `ACTIVE`
+    pub call_state: String,
+    pub caller_id_name: String,
+    pub caller_id_number: String,
+    pub destination: String,
+    pub answered: bool,
+    /// the channel this one is bridged to
+    pub other_leg: Option<String>,
+    pub hangup_cause: Option<HangupCause>,
+    /// channel variables, without the `variable_` prefix. seeded channels
+    /// have none until their next event
+    pub vars: HashMap<String, String>,
+}
+
+impl ChannelState {
+    fn from_row(row: ChannelRow) -> Self {
+        Self {
+            answered: row.callstate == "ACTIVE",
+            uuid: row.uuid,
+            name: row.name,
+            direction: row.direction,
+            state: row.state,
+            call_state: row.callstate,
+            caller_id_name: row.cid_name,
+            caller_id_number: row.cid_num,
+            destination: row.dest,
+            ..Default::default()
+        }
+    }
+
+    /// take what the event carries, keep the rest
+    fn update(&mut self, data: &EventData) {
+        let fields = [
+            (&mut self.name, "Channel-Name"),
+            (&mut self.direction, "Call-Direction"),
+            (&mut self.state, "Channel-State"),
+            (&mut self.call_state, "Channel-Call-State"),
+            (&mut self.caller_id_name, "Caller-Caller-ID-Name"),
+            (&mut self.caller_id_number, "Caller-Caller-ID-Number"),
+            (&mut self.destination, "Caller-Destination-Number"),
+        ];
+        for (field, key) in fields {
+            if let Some(value) = data.get_body_by_key(key) {
+                *field = value;
+            }
+        }
+        self.vars
+            .extend(data.vars().map(|(key, value)| (key.to_string(), value)));
+    }
+}
+
+/// a change to a [`ChannelTable`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChannelChange {
+    Added(ChannelState),
+    Updated(ChannelState),
+    Removed(ChannelState),
+    /// the table was reloaded from `show channels`, after [`ChannelTable::attach`]
+    /// or when events were lost
+    Resynced,
+}
+
+/// channels by uuid, see the [module docs](self)
+#[derive(Debug, Clone)]
+pub struct ChannelTable {
+    channels: Arc<Mutex<HashMap<String, ChannelState>>>,
+    changes: broadcast::Sender<ChannelChange>,
+    /// bumped by every attach, so the feed of a replaced connection stops
+    generation: Arc<AtomicU64>,
+}
+
+impl Default for ChannelTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ChannelTable {
+    pub fn new() -> Self {
+        Self {
+            channels: Arc::new(Mutex::new(HashMap::new())),
+            changes: broadcast::channel(1000).0,
+            generation: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// feed the table from `conn`, replacing whatever it held
+    ///
+    /// the feed stops when the connection is lost, call it again with the new
+    /// connection or use [`Self::attach_with`]. channel events are added to
+    /// the subscription
+    pub async fn attach(&self, conn: &Conn) -> Result<()> {
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        let events = self.seed(conn).await?;
+        let (table, conn) = (self.clone(), conn.clone());
+        tokio::spawn(async move {
+            table.feed(&conn, events, generation).await;
+            debug!("channel table feed {} stopped", generation);
+        });
+        Ok(())
+    }
+
+    /// like [`Self::attach`], getting a connection from `connect` and a new
+    /// one whenever it is lost. the table is resynced on every new connection
+    pub async fn attach_with<F, Fut>(&self, connect: F) -> Result<()>
+    where
+        F: Fn() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<Conn>> + Send,
+    {
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        let mut conn = connect().await?;
+        let mut events = self.seed(&conn).await?;
+        let table = self.clone();
+        tokio::spawn(async move {
+            while table.feed(&conn, events, generation).await {
+                warn!("channel table lost its connection, reconnecting");
+                (conn, events) = loop {
+                    let attempt = match connect().await {
+                        Ok(conn) => table.seed(&conn).await.map(|events| (conn, events)),
+                        Err(e) => Err(e),
+                    };
+                    match attempt {
+                        Ok(attached) => break attached,
+                        Err(e) => warn!("channel table reconnect failed: {}", e),
+                    }
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                    if table.generation.load(Ordering::SeqCst) != generation {
+                        debug!("channel table feed {} stopped", generation);
+                        return;
+                    }
+                };
+            }
+            debug!("channel table feed {} stopped", generation);
+        });
+        Ok(())
+    }
+
+    /// subscribe to channel events on `conn` and load what it has
+    async fn seed(&self, conn: &Conn) -> Result<broadcast::Receiver<Event>> {
+        // listen first so nothing falls between the seed and the feed
+        let events = conn.events();
+        reply_result(
+            conn.execute(&Command::event(EventFormat::Json, CHANNEL_EVENTS))
+                .await?,
+        )?;
+        self.resync(conn).await?;
+        Ok(events)
+    }
+
+    /// apply events until `conn` is lost, returns false when the table was
+    /// attached again in the meantime
+    async fn feed(
+        &self,
+        conn: &Conn,
+        mut events: broadcast::Receiver<Event>,
+        generation: u64,
+    ) -> bool {
+        loop {
+            if self.generation.load(Ordering::SeqCst) != generation {
+                return false;
+            }
+            let evt = tokio::select! {
+                biased;
+                evt = events.recv() => evt,
+                _ = conn.closed() => return true,
+            };
+            match evt {
+                Ok(evt) => self.apply(&evt).await,
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    warn!("channel table missed {} events, resync", n);
+                    if let Err(e) = self.resync(conn).await {
+                        warn!("channel table resync failed: {}", e);
+                    }
+                }
+                Err(broadcast::error::RecvError::Closed) => return true,
+            }
+        }
+    }
+
+    /// reload every channel from `show channels`
+    ///
+    /// channels that are new are reported as [`ChannelChange::Added`] and
+    /// channels that are gone as [`ChannelChange::Removed`], both before the
+    /// [`ChannelChange::Resynced`]
+    pub async fn resync(&self, conn: &Conn) -> Result<()> {
+        let rows = conn.show_channels().await?;
+        let mut channels = self.channels.lock().await;
+        let mut old = std::mem::take(&mut *channels);
+        for row in rows {
+            let mut state = ChannelState::from_row(row);
+            // keep the variables already learned from events
+            match old.remove(&state.uuid) {
+                Some(known) => {
+                    state.vars = known.vars;
+                    state.other_leg = known.other_leg;
+                }
+                None => {
+                    let _ = self.changes.send(ChannelChange::Added(state.clone()));
+                }
+            }
+            channels.insert(state.uuid.clone(), state);
+        }
+        for (_, state) in old {
+            let _ = self.changes.send(ChannelChange::Removed(state));
+        }
+        let _ = self.changes.send(ChannelChange::Resynced);
+        Ok(())
+    }
+
+    /// update the table from one event, events of other kinds are ignored
+    pub async fn apply(&self, evt: &Event) {
+        let Some(uuid) = evt.get_body_by_key("Unique-ID") else {
+            return;
+        };
+        let mut channels = self.channels.lock().await;
+        let change = match evt {
+            Event::ChannelDestroy(_) => match channels.remove(&uuid) {
+                Some(state) => ChannelChange::Removed(state),
+                None => return,
+            },
+            Event::ChannelCreate(data)
+            | Event::ChannelAnswer(data)
+            | Event::ChannelBridge(data)
+            | Event::ChannelUnbridge(data)
+            | Event::ChannelCallState(data)
+            | Event::ChannelHangup(data) => {
+                let added = !channels.contains_key(&uuid);
+                let state = channels
+                    .entry(uuid.clone())
+                    .or_insert_with(|| ChannelState {
+                        uuid,
+                        ..Default::default()
+                    });
+                state.update(data);
+                match evt {
+                    Event::ChannelAnswer(_) => state.answered = true,
+                    Event::ChannelBridge(_) => {
+                        state.other_leg = data.get_body_by_key("Other-Leg-Unique-ID")
+                    }
+                    Event::ChannelUnbridge(_) => state.other_leg = None,
+                    Event::ChannelHangup(_) => state.hangup_cause = data.hangup_cause(),
+                    _ => {}
+                }
+                match added {
+                    true => ChannelChange::Added(state.clone()),
+                    false => ChannelChange::Updated(state.clone()),
+                }
+            }
+            _ => return,
+        };
+        // no subscriber is fine
+        let _ = self.changes.send(change);
+    }
+
+    /// changes from now on
+    pub fn changes(&self) -> broadcast::Receiver<ChannelChange> {
+        self.changes.subscribe()
+    }
+
+    pub async fn get(&self, uuid: &str) -> Option<ChannelState> {
+        self.channels.lock().await.get(uuid).cloned()
+    }
+
+    pub async fn len(&self) -> usize {
+        self.channels.lock().await.len()
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.channels.lock().await.is_empty()
+    }
+
+    pub async fn all(&self) -> Vec<ChannelState> {
+        self.channels.lock().await.values().cloned().collect()
+    }
+
+    /// channels whose caller id number is `number`
+    pub async fn by_caller(&self, number: &str) -> Vec<ChannelState> {
+        self.filter(|state| state.caller_id_number == number).await
+    }
+
+    /// channels with the variable `name` set to `value`
+    pub async fn by_var(&self, name: &str, value: &str) -> Vec<ChannelState> {
+        self.filter(|state| state.vars.get(name).map(String::as_str) == Some(value))
+            .await
+    }
+
+    pub async fn filter(&self, f: impl Fn(&ChannelState) -> bool) -> Vec<ChannelState> {
+        self.channels
+            .lock()
+            .await
+            .values()
+            .filter(|state| f(state))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tests::{json_event, mock_freeswitch_steps},
+        Esl,
+    };
+
+    const OK: &str = "Content-Type: command/reply\nReply-Text: +OK event listener enabled json\n\n";
+
+    /// `show channels as json` answering `uuids`
+    fn show(uuids: &[&str]) -> &'static str {
+        let rows: Vec<_> = uuids
+            .iter()
+            .map(|uuid| serde_json::json!({"uuid": uuid, "callstate": "ACTIVE"}))
+            .collect();
+        let body = serde_json::json!({"row_count": rows.len(), "rows": rows}).to_string();
+        let reply = format!(
+            "Content-Type: api/response\nContent-Length: {}\n\n{}",
+            body.len(),
+            body
+        );
+        Box::leak(reply.into_boxed_str())
+    }
+
+    /// `body` framed as a json event
+    fn event(body: serde_json::Value) -> String {
+        let body = body.to_string();
+        format!(
+            "Content-Length: {}\nContent-Type: text/event-json\n\n{}",
+            body.len(),
+            body
+        )
+    }
+
+    async fn next(changes: &mut broadcast::Receiver<ChannelChange>) -> ChannelChange {
+        tokio::time::timeout(Duration::from_secs(5), changes.recv())
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    fn added(change: ChannelChange) -> String {
+        match change {
+            ChannelChange::Added(state) => state.uuid,
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    fn removed(change: ChannelChange) -> String {
+        match change {
+            ChannelChange::Removed(state) => state.uuid,
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn attach_and_resync() {
+        let create = event(
+            serde_json::json!({"Event-Name": "CHANNEL_CREATE", "Unique-ID": "c", "variable_queue": "sales"}),
+        );
+        let (addr, _) = mock_freeswitch_steps(vec![
+            (1, OK),
+            (
+                1,
+                Box::leak((show(&["a", "b"]).to_string() + &create).into_boxed_str()),
+            ),
+            (1, show(&["a", "c", "d"])),
+        ])
+        .await;
+        let conn = Esl::inbound(addr, "ClueCon").await.unwrap();
+        let table = ChannelTable::new();
+        let mut changes = table.changes();
+        table.attach(&conn).await.unwrap();
+        assert_eq!(added(next(&mut changes).await), "a");
+        assert_eq!(added(next(&mut changes).await), "b");
+        assert_eq!(next(&mut changes).await, ChannelChange::Resynced);
+        assert_eq!(added(next(&mut changes).await), "c");
+        assert_eq!(table.len().await, 3);
+
+        // d started and b ended while nobody was looking
+        table.resync(&conn).await.unwrap();
+        assert_eq!(added(next(&mut changes).await), "d");
+        assert_eq!(removed(next(&mut changes).await), "b");
+        assert_eq!(next(&mut changes).await, ChannelChange::Resynced);
+        assert!(table.get("b").await.is_none());
+        assert_eq!(table.by_var("queue", "sales").await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn lag_resyncs() {
+        // c is created and b destroyed in a burst the feed cannot keep up with,
+        // it is held up on the first event
+        let mut burst =
+            event(serde_json::json!({"Event-Name": "CHANNEL_CALLSTATE", "Unique-ID": "a"}));
+        burst += &event(serde_json::json!({"Event-Name": "CHANNEL_CREATE", "Unique-ID": "c"}));
+        burst += &event(serde_json::json!({"Event-Name": "CHANNEL_DESTROY", "Unique-ID": "b"}));
+        burst += &event(serde_json::json!({"Event-Name": "HEARTBEAT"})).repeat(1500);
+        burst += &event(serde_json::json!({"Event-Name": "RE_SCHEDULE"}));
+        let status = "Content-Type: api/response\nContent-Length: 3\n\nUP\n".to_string() + &burst;
+        let (addr, _) = mock_freeswitch_steps(vec![
+            (1, OK),
+            (1, show(&["a", "b"])),
+            (1, Box::leak(status.into_boxed_str())),
+            (1, show(&["a", "c"])),
+        ])
+        .await;
+        let mut conn = Esl::inbound(addr, "ClueCon").await.unwrap();
+        let table = ChannelTable::new();
+        let mut changes = table.changes();
+        table.attach(&conn).await.unwrap();
+        assert_eq!(added(next(&mut changes).await), "a");
+        assert_eq!(added(next(&mut changes).await), "b");
+        assert_eq!(next(&mut changes).await, ChannelChange::Resynced);
+
+        // hold the feed up until the whole burst went by
+        let mut events = conn.events();
+        let channels = table.channels.lock().await;
+        assert_eq!(conn.api("status").await.unwrap(), "UP\n");
+        loop {
+            match events.recv().await {
+                Ok(evt) if evt.get_event_name().as_deref() == Some("RE_SCHEDULE") => break,
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(e) => panic!("{}", e),
+            }
+        }
+        drop(channels);
+
+        assert!(
+            matches!(next(&mut changes).await, ChannelChange::Updated(state) if state.uuid == "a")
+        );
+        assert_eq!(added(next(&mut changes).await), "c");
+        assert_eq!(removed(next(&mut changes).await), "b");
+        assert_eq!(next(&mut changes).await, ChannelChange::Resynced);
+        let mut uuids: Vec<_> = table
+            .all()
+            .await
+            .into_iter()
+            .map(|state| state.uuid)
+            .collect();
+        uuids.sort();
+        assert_eq!(uuids, ["a", "c"]);
+    }
+
+    #[tokio::test]
+    async fn attach_with_reconnects() {
+        let (first, _) = mock_freeswitch_steps(vec![
+            (1, OK),
+            (
+                1,
+                Box::leak(
+                    (show(&["a", "b"]).to_string()
+                        + "Content-Type: text/disconnect-notice\nContent-Length: 3\n\nbye")
+                        .into_boxed_str(),
+                ),
+            ),
+        ])
+        .await;
+        let (second, _) = mock_freeswitch_steps(vec![(1, OK), (1, show(&["a"]))]).await;
+        let attempts = Arc::new(AtomicU64::new(0));
+        let table = ChannelTable::new();
+        let mut changes = table.changes();
+        table
+            .attach_with(move || {
+                let addr = match attempts.fetch_add(1, Ordering::SeqCst) {
+                    0 => first,
+                    _ => second,
+                };
+                Esl::inbound(addr, "ClueCon")
+            })
+            .await
+            .unwrap();
+        assert_eq!(added(next(&mut changes).await), "a");
+        assert_eq!(added(next(&mut changes).await), "b");
+        assert_eq!(next(&mut changes).await, ChannelChange::Resynced);
+        assert_eq!(removed(next(&mut changes).await), "b");
+        assert_eq!(next(&mut changes).await, ChannelChange::Resynced);
+        assert_eq!(table.len().await, 1);
+    }
+
+    #[tokio::test]
+    async fn follow_events() {
+        let table = ChannelTable::new();
+        let mut changes = table.changes();
+        table
+            .apply(&json_event(serde_json::json!({"Event-Name": "CHANNEL_CREATE", "Unique-ID": "a", "Caller-Caller-ID-Number": "1000", "variable_queue": "sales"})))
+            .await;
+        assert!(matches!(changes.recv().await, Ok(ChannelChange::Added(_))));
+        table
+            .apply(&json_event(serde_json::json!({"Event-Name": "CHANNEL_BRIDGE", "Unique-ID": "a", "Other-Leg-Unique-ID": "b"})))
+            .await;
+        let state = table.get("a").await.unwrap();
+        assert_eq!(state.other_leg.as_deref(), Some("b"));
+        assert_eq!(state.caller_id_number, "1000");
+        assert_eq!(table.by_caller("1000").await.len(), 1);
+        assert_eq!(table.by_var("queue", "sales").await.len(), 1);
+
+        table
+            .apply(&json_event(serde_json::json!({"Event-Name": "CHANNEL_HANGUP", "Unique-ID": "a", "Hangup-Cause": "NORMAL_CLEARING"})))
+            .await;
+        table
+            .apply(&json_event(
+                serde_json::json!({"Event-Name": "CHANNEL_DESTROY", "Unique-ID": "a"}),
+            ))
+            .await;
+        assert!(matches!(
+            changes.recv().await,
+            Ok(ChannelChange::Updated(_))
+        ));
+        assert!(matches!(
+            changes.recv().await,
+            Ok(ChannelChange::Updated(_))
+        ));
+        match changes.recv().await {
+            Ok(ChannelChange::Removed(state)) => {
+                assert_eq!(state.hangup_cause, Some(HangupCause::NormalClearing))
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(table.is_empty().await);
+    }
+}
@@ -0,0 +1,207 @@
+//! esl framing
+//!
+//! a frame is a block of `Key: Value` lines ended by an empty line, followed by
+//! `Content-Length` bytes of body when that header is present. [`EslCodec`]
+//! parses frames incrementally out of a `BytesMut`: the header terminator is
+//! searched only in bytes not seen before, and bodies are split off without
+//! copying.
+
+use bytes::{Bytes, BytesMut};
+use std::collections::HashMap;
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::{
+    command::Command,
+    error::{EslError, Result},
+    event::EventData,
+};
+
+/// one esl message, either a reply, an api response or an event
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Frame {
+    pub headers: HashMap<String, String>,
+    pub body: Option<Bytes>,
+}
+
+impl Frame {
+    pub fn header(&self, key: &str) -> Option<&str> {
+        self.headers.get(key).map(|s| s.as_str())
+    }
+
+    pub fn content_type(&self) -> Option<&str> {
+        self.header("Content-Type")
+    }
+}
+
+impl From<Frame> for EventData {
+    fn from(frame: Frame) -> Self {
+        let body = frame
+            .body
+            .map(|body| String::from_utf8_lossy(&body).into_owned());
+        EventData::new(frame.headers, body)
+    }
+}
+
+/// parse a header block, each line is `Key: Value`, the value may contain `:`
+pub(crate) fn parse_headers(block: &[u8]) -> HashMap<String, String> {
+    let block = String::from_utf8_lossy(block);
+    block
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            Some((key.trim().to_string(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+/// `tokio_util` codec for esl frames
+///
+/// the decoding itself does not depend on any runtime, see [`Self::decode_frame`]
+#[derive(Debug, Default)]
+pub struct EslCodec {
+    /// bytes of the current header block already searched for `\n\n`
+    scanned: usize,
+    /// parsed headers waiting for a body of the given length
+    pending: Option<(HashMap<String, String>, usize)>,
+}
+
+impl EslCodec {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// take the next complete frame off the front of `buf`
+    pub fn decode_frame(&mut self, buf: &mut BytesMut) -> Result<Option<Frame>> {
+        if self.pending.is_none() {
+            // tolerate blank lines between frames
+            if self.scanned == 0 {
+                let blank = buf
+                    .iter()
+                    .take_while(|c| **c == b'\n' || **c == b'\r')
+                    .count();
+                let _ = buf.split_to(blank);
+            }
+            // the terminator may straddle the previous read, so back up one byte
+            let from = self.scanned.saturating_sub(1);
+            let Some(end) = buf[from..]
+                .windows(2)
+                .position(|w| w == b"\n\n")
+                .map(|i| from + i)
+            else {
+                self.scanned = buf.len();
+                return Ok(None);
+            };
+            self.scanned = 0;
+            let block = buf.split_to(end + 2);
+            let headers = parse_headers(&block[..end]);
+            let content_length = match headers.get("Content-Length") {
+                Some(len) => len.parse::<usize>().map_err(|e| {
+                    EslError::ProtocolError(format!("invalid Content-Length {:?}: {}", len, e))
+                })?,
+                None => 0,
+            };
+            if content_length == 0 {
+                return Ok(Some(Frame {
+                    headers,
+                    body: None,
+                }));
+            }
+            self.pending = Some((headers, content_length));
+        }
+
+        let Some((_, content_length)) = self.pending else {
+            return Ok(None);
+        };
+        if buf.len() < content_length {
+            buf.reserve(content_length - buf.len());
+            return Ok(None);
+        }
+        let body = buf.split_to(content_length).freeze();
+        let (headers, _) = self.pending.take().unwrap_or_default();
+        Ok(Some(Frame {
+            headers,
+            body: Some(body),
+        }))
+    }
+}
+
+impl Decoder for EslCodec {
+    type Item = Frame;
+    type Error = EslError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Frame>> {
+        self.decode_frame(src)
+    }
+}
+
+/// commands are written as is, they carry their own `\n\n` terminator
+impl Encoder<String> for EslCodec {
+    type Error = EslError;
+
+    fn encode(&mut self, item: String, dst: &mut BytesMut) -> Result<()> {
+        dst.extend_from_slice(item.as_bytes());
+        Ok(())
+    }
+}
+
+/// commands are checked and terminated by [`Command::encode`]
+impl Encoder<Command> for EslCodec {
+    type Error = EslError;
+
+    fn encode(&mut self, item: Command, dst: &mut BytesMut) -> Result<()> {
+        dst.extend_from_slice(item.encode()?.as_bytes());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRAFFIC: &str = "Content-Type: auth/request\n\n\
+        Content-Type: command/reply\nReply-Text: +OK Job-UUID: 7f4d\n\n\
+        Content-Type: api/response\nContent-Length: 4\n\n+OK\n\
+        Content-Length: 27\nContent-Type: text/event-json\n\n{\"Event-Name\":\"HEARTBEAT\"}\n";
+
+    fn decode_all(codec: &mut EslCodec, buf: &mut BytesMut) -> Vec<Frame> {
+        let mut frames = Vec::new();
+        while let Some(frame) = codec.decode_frame(buf).unwrap() {
+            frames.push(frame);
+        }
+        frames
+    }
+
+    #[test]
+    fn multiple_frames_per_read() {
+        let mut codec = EslCodec::new();
+        let mut buf = BytesMut::from(TRAFFIC);
+        let frames = decode_all(&mut codec, &mut buf);
+        assert_eq!(frames.len(), 4);
+        assert_eq!(frames[0].content_type(), Some("auth/request"));
+        assert_eq!(frames[1].header("Reply-Text"), Some("+OK Job-UUID: 7f4d"));
+        assert_eq!(frames[2].body.as_deref(), Some(&b"+OK\n"[..]));
+        let evt: crate::event::Event = EventData::from(frames[3].clone()).into();
+        assert!(matches!(evt, crate::event::Event::Heartbeat(_)));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn byte_by_byte() {
+        let mut codec = EslCodec::new();
+        let mut buf = BytesMut::new();
+        let mut frames = Vec::new();
+        for b in TRAFFIC.as_bytes() {
+            buf.extend_from_slice(&[*b]);
+            frames.extend(decode_all(&mut codec, &mut buf));
+        }
+        let mut whole = BytesMut::from(TRAFFIC);
+        assert_eq!(frames, decode_all(&mut EslCodec::new(), &mut whole));
+    }
+
+    #[test]
+    fn invalid_content_length() {
+        let mut buf = BytesMut::from("Content-Length: x\n\n");
+        let err = EslCodec::new().decode_frame(&mut buf).unwrap_err();
+        assert!(matches!(err, EslError::ProtocolError(_)));
+    }
+}
@@ -0,0 +1,422 @@
+//! socket commands and their wire encoding
+//!
+//! every command goes through [`Command::encode`], which rejects arguments
+//! that would break out of the command line (`\r`, `\n`) and frames bodies
+//! with `Content-Length`. the command line has no escape syntax, so nothing is
+//! escaped: multi-line data belongs in the body.
+//!
+//! ```
+//! use esl_rs::command::{Command, EventFormat};
+//!
+//! let cmd = Command::event(EventFormat::Json, ["CHANNEL_CREATE", "CHANNEL_ANSWER"]);
+//! assert_eq!(cmd.encode().unwrap(), "event json CHANNEL_CREATE CHANNEL_ANSWER\n\n");
+//!
+//! let cmd = Command::sendmsg(Some("7f4d"))
+//!     .header("call-command", "execute")
+//!     .header("execute-app-name", "playback")
+//!     .header("execute-app-arg", "ivr/ivr-welcome.wav");
+//! assert!(cmd.encode().unwrap().starts_with("sendmsg 7f4d\ncall-command: execute\n"));
+//!
+//! assert!(Command::api("status\n\nexit").encode().is_err());
+//! ```
+
+use std::fmt;
+
+use crate::error::{EslError, Result};
+
+/// event serialization requested by `event` and `myevents`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EventFormat {
+    Plain,
+    #[default]
+    Json,
+    Xml,
+}
+
+impl fmt::Display for EventFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Plain => "plain",
+            Self::Json => "json",
+            Self::Xml => "xml",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// `auth <password>`
+    Auth { password: String },
+    /// `api <command>`, answered by `api/response`
+    Api { command: String },
+    /// `bgapi <command>`, the result arrives as a BACKGROUND_JOB event
+    Bgapi {
+        command: String,
+        job_uuid: Option<String>,
+    },
+    /// `event <format> <events>`
+    Event {
+        format: EventFormat,
+        events: Vec<String>,
+    },
+    /// `nixevent <events>`
+    NixEvent { events: Vec<String> },
+    /// `filter <header> <value>`
+    Filter { header: String, value: String },
+    /// `filter delete <header> [<value>]`
+    FilterDelete {
+        header: String,
+        value: Option<String>,
+    },
+    /// `sendmsg [<uuid>]` with `call-command` and friends as headers
+    SendMsg {
+        uuid: Option<String>,
+        headers: Vec<(String, String)>,
+        body: Option<String>,
+    },
+    /// `sendevent <event-name>`
+    SendEvent {
+        name: String,
+        headers: Vec<(String, String)>,
+        body: Option<String>,
+    },
+    /// `linger [<seconds>]`
+    Linger { seconds: Option<u32> },
+    /// `myevents [<uuid>] [<format>]`, the uuid is omitted on outbound sockets
+    MyEvents {
+        uuid: Option<String>,
+        format: Option<EventFormat>,
+    },
+    /// `log <level>`
+    Log { level: String },
+    /// `exit`
+    Exit,
+}
+
+impl Command {
+    pub fn auth(password: impl Into<String>) -> Self {
+        Self::Auth {
+            password: password.into(),
+        }
+    }
+
+    pub fn api(command: impl Into<String>) -> Self {
+        Self::Api {
+            command: command.into(),
+        }
+    }
+
+    pub fn bgapi(command: impl Into<String>, job_uuid: Option<String>) -> Self {
+        Self::Bgapi {
+            command: command.into(),
+            job_uuid,
+        }
+    }
+
+    pub fn event<S: Into<String>>(
+        format: EventFormat,
+        events: impl IntoIterator<Item = S>,
+    ) -> Self {
+        Self::Event {
+            format,
+            events: events.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    pub fn nixevent<S: Into<String>>(events: impl IntoIterator<Item = S>) -> Self {
+        Self::NixEvent {
+            events: events.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    pub fn filter(header: impl Into<String>, value: impl Into<String>) -> Self {
+        Self::Filter {
+            header: header.into(),
+            value: value.into(),
+        }
+    }
+
+    pub fn filter_delete(header: impl Into<String>, value: Option<String>) -> Self {
+        Self::FilterDelete {
+            header: header.into(),
+            value,
+        }
+    }
+
+    pub fn sendmsg(uuid: Option<&str>) -> Self {
+        Self::SendMsg {
+            uuid: uuid.map(str::to_string),
+            headers: Vec::new(),
+            body: None,
+        }
+    }
+
+    pub fn sendevent(name: impl Into<String>) -> Self {
+        Self::SendEvent {
+            name: name.into(),
+            headers: Vec::new(),
+            body: None,
+        }
+    }
+
+    /// add a header to `sendmsg`/`sendevent`, ignored by other commands
+    pub fn header(mut self, key: impl Into<String>, value: impl ToString) -> Self {
+        if let Self::SendMsg { headers, .. } | Self::SendEvent { headers, .. } = &mut self {
+            headers.push((key.into(), value.to_string()));
+        }
+        self
+    }
+
+    /// set the body of `sendmsg`/`sendevent`, ignored by other commands
+    pub fn body(mut self, text: impl Into<String>) -> Self {
+        if let Self::SendMsg { body, .. } | Self::SendEvent { body, .. } = &mut self {
+            *body = Some(text.into());
+        }
+        self
+    }
+
+    /// the wire form, terminated by `\n\n`
+    ///
+    /// arguments are checked, not escaped: one with a line break, or an empty
+    /// one, is an [`EslError::InvalidArgument`], everything else is written as
+    /// it is. quoting inside an api command is up to that command
+    pub fn encode(&self) -> Result<String> {
+        let mut out = String::new();
+        let mut headers: &[(String, String)] = &[];
+        let mut body = None;
+        match self {
+            Self::Auth { password } => line(&mut out, &["auth", arg(password)?]),
+            Self::Api { command } => line(&mut out, &["api", arg(command)?]),
+            Self::Bgapi { command, job_uuid } => {
+                line(&mut out, &["bgapi", arg(command)?]);
+                if let Some(uuid) = job_uuid {
+                    header(&mut out, "Job-UUID", uuid)?;
+                }
+            }
+            Self::Event { format, events } => {
+                let format = format.to_string();
+                line(&mut out, &["event", &format, &list(events)?])
+            }
+            Self::NixEvent { events } => line(&mut out, &["nixevent", &list(events)?]),
+            Self::Filter { header, value } => {
+                line(&mut out, &["filter", arg(header)?, arg(value)?])
+            }
+            Self::FilterDelete { header, value } => {
+                line(&mut out, &["filter delete", arg(header)?, opt(value)?])
+            }
+            Self::SendMsg {
+                uuid,
+                headers: h,
+                body: b,
+            } => {
+                line(&mut out, &["sendmsg", opt(uuid)?]);
+                (headers, body) = (h, b.as_deref());
+            }
+            Self::SendEvent {
+                name,
+                headers: h,
+                body: b,
+            } => {
+                line(&mut out, &["sendevent", arg(name)?]);
+                (headers, body) = (h, b.as_deref());
+            }
+            Self::Linger { seconds } => {
+                let seconds = seconds.map(|s| s.to_string()).unwrap_or_default();
+                line(&mut out, &["linger", &seconds])
+            }
+            Self::MyEvents { uuid, format } => {
+                let format = format.map(|f| f.to_string()).unwrap_or_default();
+                line(&mut out, &["myevents", opt(uuid)?, &format])
+            }
+            Self::Log { level } => line(&mut out, &["log", arg(level)?]),
+            Self::Exit => line(&mut out, &["exit"]),
+        }
+        for (key, value) in headers {
+            header(&mut out, key, value)?;
+        }
+        if let Some(body) = body {
+            header(&mut out, "Content-Length", &body.len().to_string())?;
+            out.push('\n');
+            out.push_str(body);
+        } else {
+            out.push('\n');
+        }
+        Ok(out)
+    }
+
+    /// the command word, e.g. `api` or `filter delete`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Auth { .. } => "auth",
+            Self::Api { .. } => "api",
+            Self::Bgapi { .. } => "bgapi",
+            Self::Event { .. } => "event",
+            Self::NixEvent { .. } => "nixevent",
+            Self::Filter { .. } => "filter",
+            Self::FilterDelete { .. } => "filter delete",
+            Self::SendMsg { .. } => "sendmsg",
+            Self::SendEvent { .. } => "sendevent",
+            Self::Linger { .. } => "linger",
+            Self::MyEvents { .. } => "myevents",
+            Self::Log { .. } => "log",
+            Self::Exit => "exit",
+        }
+    }
+}
+
+impl fmt::Display for Command {
+    /// the command line, without headers or body. just the name when the
+    /// arguments do not encode
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.encode() {
+            Ok(encoded) => f.write_str(command_line(&encoded)),
+            Err(_) => f.write_str(self.name()),
+        }
+    }
+}
+
+/// the first line of an encoded command, for logs and errors
+pub(crate) fn command_line(encoded: &str) -> &str {
+    encoded.lines().next().unwrap_or_default()
+}
+
+/// a raw command terminated for the wire
+///
+/// its lines are written as they are, a blank one would end it early and
+/// start another command, so that is rejected
+pub(crate) fn terminate(command: &str) -> Result<String> {
+    if command.split('\n').any(|line| line.trim().is_empty()) {
+        return Err(EslError::InvalidArgument(format!(
+            "blank line in {:?}",
+            command
+        )));
+    }
+    Ok(format!("{}\n\n", command))
+}
+
+/// a single argument must stay on the command line
+fn arg(value: &str) -> Result<&str> {
+    if value.contains(['\r', '\n']) {
+        return Err(EslError::InvalidArgument(format!(
+            "line break in {:?}",
+            value
+        )));
+    }
+    if value.trim().is_empty() {
+        return Err(EslError::InvalidArgument("empty argument".to_string()));
+    }
+    Ok(value)
+}
+
+/// an optional argument, left out when `None`
+fn opt(value: &Option<String>) -> Result<&str> {
+    value.as_deref().map_or(Ok(""), arg)
+}
+
+/// event names, separated by spaces
+fn list(events: &[String]) -> Result<String> {
+    let events = events
+        .iter()
+        .map(|evt| arg(evt.trim()))
+        .collect::<Result<Vec<_>>>()?;
+    if events.is_empty() {
+        return Err(EslError::InvalidArgument("no events".to_string()));
+    }
+    Ok(events.join(" "))
+}
+
+/// the command line, empty optional parts are skipped
+fn line(out: &mut String, parts: &[&str]) {
+    let parts: Vec<_> = parts
+        .iter()
+        .filter(|part| !part.is_empty())
+        .copied()
+        .collect();
+    out.push_str(&parts.join(" "));
+    out.push('\n');
+}
+
+fn header(out: &mut String, key: &str, value: &str) -> Result<()> {
+    if key.contains(':') || arg(key).is_err() {
+        return Err(EslError::InvalidArgument(format!("header name {:?}", key)));
+    }
+    if value.contains(['\r', '\n']) {
+        return Err(EslError::InvalidArgument(format!(
+            "line break in header {}, send it as the body",
+            key
+        )));
+    }
+    out.push_str(key);
+    out.push_str(": ");
+    out.push_str(value);
+    out.push('\n');
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_commands() {
+        let encode = |cmd: Command| cmd.encode().unwrap();
+        assert_eq!(encode(Command::auth("ClueCon")), "auth ClueCon\n\n");
+        assert_eq!(
+            encode(Command::bgapi("status", Some("7f4d".to_string()))),
+            "bgapi status\nJob-UUID: 7f4d\n\n"
+        );
+        assert_eq!(encode(Command::nixevent(["ALL"])), "nixevent ALL\n\n");
+        assert_eq!(
+            encode(Command::filter_delete("Unique-ID", None)),
+            "filter delete Unique-ID\n\n"
+        );
+        assert_eq!(encode(Command::Linger { seconds: Some(5) }), "linger 5\n\n");
+        assert_eq!(
+            encode(Command::MyEvents {
+                uuid: Some("7f4d".to_string()),
+                format: Some(EventFormat::Json),
+            }),
+            "myevents 7f4d json\n\n"
+        );
+        assert_eq!(encode(Command::Exit), "exit\n\n");
+        assert_eq!(
+            encode(
+                Command::sendevent("CUSTOM")
+                    .header("Event-Subclass", "my::event")
+                    .body("a\nb")
+            ),
+            "sendevent CUSTOM\nEvent-Subclass: my::event\nContent-Length: 3\n\na\nb"
+        );
+        assert_eq!(Command::api("status").to_string(), "api status");
+    }
+
+    #[test]
+    fn reject_injection() {
+        let invalid = |cmd: Command| matches!(cmd.encode(), Err(EslError::InvalidArgument(_)));
+        assert!(invalid(Command::api("status\n\nexit")));
+        assert!(invalid(Command::bgapi("originate x &park\r\nexit", None)));
+        assert!(invalid(Command::event(EventFormat::Json, ["ALL\nexit"])));
+        assert!(invalid(Command::event(
+            EventFormat::Json,
+            Vec::<String>::new()
+        )));
+        assert!(invalid(Command::sendmsg(None).header("a", "b\n\nexit")));
+        assert!(invalid(Command::sendmsg(None).header("a: b\nc", "d")));
+        assert!(invalid(Command::api("")));
+        assert_eq!(Command::api("status\n\nexit").to_string(), "api");
+
+        // raw commands keep their lines but cannot end early
+        assert_eq!(
+            terminate("bgapi status\nJob-UUID: 7f4d").unwrap(),
+            "bgapi status\nJob-UUID: 7f4d\n\n"
+        );
+        for raw in [
+            "",
+            "api status\n",
+            "api status\r\n\r\nexit",
+            "api status\n \nexit",
+        ] {
+            assert!(terminate(raw).is_err(), "{:?}", raw);
+        }
+    }
+}
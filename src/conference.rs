@@ -0,0 +1,489 @@
+//! `conference` commands and a member roster
+//!
+//! [`Conference`] wraps the `conference <name> ...` api. [`Roster`] follows
+//! the `conference::maniacal` CUSTOM events, subscribe them with
+//! [`CONFERENCE_EVENTS`], and keeps the members of every conference and who
+//! is talking. like [`crate::call::CallGraph`] it is a plain state machine.
+//!
+//! ```no_run
+//! # async fn demo(conn: esl_rs::conn::Conn) -> esl_rs::error::Result<()> {
+//! use esl_rs::conference::Members;
+//!
+//! let room = conn.conference("3000")?;
+//! for member in room.list().await? {
+//!     println!("{} {} talking: {}", member.id, member.caller_id_number, member.talking);
+//! }
+//! room.mute(Members::NonModerator).await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+};
+
+use crate::{
+    channel::{quote, token},
+    command::Command,
+    conn::Conn,
+    error::{EslError, Result},
+    event::{Event, EventData},
+    reply::api_result,
+};
+
+/// what to subscribe for a [`Roster`]
+pub const CONFERENCE_EVENTS: [&str; 2] = ["CUSTOM", "conference::maniacal"];
+
+const SUBCLASS: &str = "conference::maniacal";
+
+/// which members a command applies to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Members {
+    Id(u32),
+    All,
+    /// the member who joined last
+    Last,
+    NonModerator,
+}
+
+impl fmt::Display for Members {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Id(id) => write!(f, "{}", id),
+            Self::All => f.write_str("all"),
+            Self::Last => f.write_str("last"),
+            Self::NonModerator => f.write_str("non_moderator"),
+        }
+    }
+}
+
+/// a member, from `conference <name> list` or conference events
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Member {
+    pub id: u32,
+    pub uuid: String,
+    /// the channel name, e.g. `sofia/internal/1000@10.0.0.1`
+    pub endpoint: String,
+    pub caller_id_name: String,
+    pub caller_id_number: String,
+    /// can be heard, `false` when muted
+    pub speak: bool,
+    /// hears the conference, `false` when deaf
+    pub hear: bool,
+    pub talking: bool,
+    /// has the floor, the loudest talker
+    pub floor: bool,
+    pub moderator: bool,
+    pub volume_in: Option<i32>,
+    pub volume_out: Option<i32>,
+    pub energy: Option<i32>,
+}
+
+impl Member {
+    /// a line of `conference <name> list`,
+    /// `id;endpoint;uuid;cid_name;cid_num;flags;volume_in;volume_out;energy`
+    fn from_list_line(line: &str) -> Option<Self> {
+        let fields: Vec<_> = line.split(';').collect();
+        if fields.len() < 6 {
+            return None;
+        }
+        let flags: Vec<_> = fields[5].split('|').collect();
+        let number = |i: usize| fields.get(i).and_then(|v| v.trim().parse().ok());
+        Some(Self {
+            id: fields[0].trim().parse().ok()?,
+            endpoint: fields[1].to_string(),
+            uuid: fields[2].to_string(),
+            caller_id_name: fields[3].to_string(),
+            caller_id_number: fields[4].to_string(),
+            speak: flags.contains(&"speak"),
+            hear: flags.contains(&"hear"),
+            talking: flags.contains(&"talking"),
+            floor: flags.contains(&"floor"),
+            moderator: flags.contains(&"moderator"),
+            volume_in: number(6),
+            volume_out: number(7),
+            energy: number(8),
+        })
+    }
+
+    /// take the member fields every conference event carries
+    fn update(&mut self, data: &EventData) {
+        let flag = |key: &str| data.get_body_by_key(key).map(|value| value == "true");
+        let fields = [
+            (&mut self.uuid, "Unique-ID"),
+            (&mut self.endpoint, "Channel-Name"),
+            (&mut self.caller_id_name, "Caller-Caller-ID-Name"),
+            (&mut self.caller_id_number, "Caller-Caller-ID-Number"),
+        ];
+        for (field, key) in fields {
+            if let Some(value) = data.get_body_by_key(key) {
+                *field = value;
+            }
+        }
+        let flags = [
+            (&mut self.speak, "Speak"),
+            (&mut self.hear, "Hear"),
+            (&mut self.talking, "Talking"),
+            (&mut self.floor, "Floor"),
+        ];
+        for (field, key) in flags {
+            if let Some(value) = flag(key) {
+                *field = value;
+            }
+        }
+        if let Some(kind) = data.get_body_by_key("Member-Type") {
+            self.moderator = kind == "moderator";
+        }
+        if let Some(level) = data
+            .get_body_by_key("Energy-Level")
+            .and_then(|level| level.trim().parse().ok())
+        {
+            self.energy = Some(level);
+        }
+    }
+}
+
+/// a conference on a [`Conn`], see [`Conn::conference`]
+#[derive(Debug, Clone)]
+pub struct Conference {
+    conn: Conn,
+    name: String,
+}
+
+impl Conn {
+    /// control the conference `name`, fails when it is not a single token
+    pub fn conference(&self, name: impl Into<String>) -> Result<Conference> {
+        let name = name.into();
+        token(&name)?;
+        Ok(Conference {
+            conn: self.clone(),
+            name,
+        })
+    }
+}
+
+impl Conference {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// `list`, the current members
+    pub async fn list(&self) -> Result<Vec<Member>> {
+        let text = self.api("list", &[]).await?;
+        Ok(text.lines().filter_map(Member::from_list_line).collect())
+    }
+
+    pub async fn kick(&self, members: Members) -> Result<()> {
+        self.member_api("kick", members, &[]).await
+    }
+
+    pub async fn mute(&self, members: Members) -> Result<()> {
+        self.member_api("mute", members, &[]).await
+    }
+
+    pub async fn unmute(&self, members: Members) -> Result<()> {
+        self.member_api("unmute", members, &[]).await
+    }
+
+    pub async fn deaf(&self, members: Members) -> Result<()> {
+        self.member_api("deaf", members, &[]).await
+    }
+
+    pub async fn undeaf(&self, members: Members) -> Result<()> {
+        self.member_api("undeaf", members, &[]).await
+    }
+
+    /// the energy level below which a member is not heard
+    pub async fn energy(&self, members: Members, level: u32) -> Result<()> {
+        self.member_api("energy", members, &[&level.to_string()])
+            .await
+    }
+
+    /// `volume_in`, from -4 to 4
+    pub async fn volume_in(&self, members: Members, level: i32) -> Result<()> {
+        self.member_api("volume_in", members, &[&level.to_string()])
+            .await
+    }
+
+    /// `volume_out`, from -4 to 4
+    pub async fn volume_out(&self, members: Members, level: i32) -> Result<()> {
+        self.member_api("volume_out", members, &[&level.to_string()])
+            .await
+    }
+
+    /// play `file` to the whole conference, or to one member
+    pub async fn play(&self, file: &str, member: Option<u32>) -> Result<()> {
+        let member = member.map(|id| id.to_string()).unwrap_or_default();
+        self.api("play", &[&quote(file)?, &member]).await?;
+        Ok(())
+    }
+
+    /// `stop all`, stop every file playing
+    pub async fn stop(&self) -> Result<()> {
+        self.api("stop", &["all"]).await?;
+        Ok(())
+    }
+
+    /// `record`, record the conference to `path`
+    pub async fn record(&self, path: &str) -> Result<()> {
+        self.api("record", &[&quote(path)?]).await?;
+        Ok(())
+    }
+
+    /// `norecord`, `all` stops every recording
+    pub async fn stop_record(&self, path: &str) -> Result<()> {
+        self.api("norecord", &[&quote(path)?]).await?;
+        Ok(())
+    }
+
+    /// stop new members from joining
+    pub async fn lock(&self) -> Result<()> {
+        self.api("lock", &[]).await?;
+        Ok(())
+    }
+
+    pub async fn unlock(&self) -> Result<()> {
+        self.api("unlock", &[]).await?;
+        Ok(())
+    }
+
+    /// give member `id` the floor
+    pub async fn floor(&self, id: u32) -> Result<()> {
+        self.api("floor", &[&id.to_string()]).await?;
+        Ok(())
+    }
+
+    async fn member_api(&self, command: &str, members: Members, args: &[&str]) -> Result<()> {
+        let members = members.to_string();
+        let mut all = vec![members.as_str()];
+        all.extend_from_slice(args);
+        self.api(command, &all).await?;
+        Ok(())
+    }
+
+    /// `conference <name> <command> <args>`, empty arguments are left out
+    async fn api(&self, command: &str, args: &[&str]) -> Result<String> {
+        let mut line = format!("conference {} {}", self.name, command);
+        for arg in args.iter().filter(|arg| !arg.is_empty()) {
+            line.push(' ');
+            line.push_str(arg);
+        }
+        let text = api_result(self.conn.execute(&Command::api(line)).await?)?;
+        // errors come back without -ERR
+        let first = text.lines().next().unwrap_or_default();
+        if first.starts_with("Conference ") && first.ends_with("not found") {
+            return Err(EslError::ApiError(first.to_string()));
+        }
+        if first.starts_with("Non-Existant ID") {
+            return Err(EslError::InvalidArgument(first.to_string()));
+        }
+        Ok(text)
+    }
+}
+
+/// a change to a [`Roster`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RosterChange {
+    Created(String),
+    Destroyed(String),
+    Joined { conference: String, member: Member },
+    Left { conference: String, member: Member },
+    Updated { conference: String, member: Member },
+}
+
+/// members of every conference, by member id
+#[derive(Debug, Default)]
+pub struct Roster {
+    conferences: HashMap<String, BTreeMap<u32, Member>>,
+}
+
+impl Roster {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// seed a conference from [`Conference::list`]
+    pub fn load(&mut self, conference: &str, members: Vec<Member>) {
+        self.conferences.insert(
+            conference.to_string(),
+            members
+                .into_iter()
+                .map(|member| (member.id, member))
+                .collect(),
+        );
+    }
+
+    pub fn members(&self, conference: &str) -> impl Iterator<Item = &Member> {
+        self.conferences
+            .get(conference)
+            .into_iter()
+            .flat_map(BTreeMap::values)
+    }
+
+    pub fn member(&self, conference: &str, id: u32) -> Option<&Member> {
+        self.conferences.get(conference)?.get(&id)
+    }
+
+    /// members currently talking
+    pub fn talking(&self, conference: &str) -> impl Iterator<Item = &Member> {
+        self.members(conference).filter(|member| member.talking)
+    }
+
+    pub fn conferences(&self) -> impl Iterator<Item = &str> {
+        self.conferences.keys().map(String::as_str)
+    }
+
+    /// update from one event, other events are ignored
+    pub fn push(&mut self, evt: &Event) -> Option<RosterChange> {
+        let Event::Custom(data) = evt else {
+            return None;
+        };
+        if data.get_subclass().as_deref() != Some(SUBCLASS) {
+            return None;
+        }
+        let conference = data.get_body_by_key("Conference-Name")?;
+        let action = data.get_body_by_key("Action")?;
+        match action.as_str() {
+            "conference-create" => {
+                self.conferences.entry(conference.clone()).or_default();
+                return Some(RosterChange::Created(conference));
+            }
+            "conference-destroy" => {
+                self.conferences.remove(&conference);
+                return Some(RosterChange::Destroyed(conference));
+            }
+            _ => {}
+        }
+        let members = self.conferences.entry(conference.clone()).or_default();
+        if action == "floor-change" {
+            // the floor moves from Old-ID to New-ID, which is `none` when
+            // nobody has it and the event then carries no member
+            let floor_id = |key| {
+                data.get_body_by_key(key)
+                    .and_then(|id| id.trim().parse::<u32>().ok())
+            };
+            let (old, new) = (floor_id("Old-ID"), floor_id("New-ID"));
+            for member in members.values_mut() {
+                member.floor = Some(member.id) == new;
+            }
+            let member = match new.and_then(|id| members.get_mut(&id)) {
+                Some(member) => {
+                    member.update(data);
+                    member.clone()
+                }
+                None => members.get(&old?)?.clone(),
+            };
+            return Some(RosterChange::Updated { conference, member });
+        }
+        let id = data
+            .get_body_by_key("Member-ID")?
+            .trim()
+            .parse::<u32>()
+            .ok()?;
+        if action == "del-member" {
+            let mut member = members.remove(&id)?;
+            member.update(data);
+            return Some(RosterChange::Left { conference, member });
+        }
+        // a member whose add-member was missed is picked up as an update
+        let joined = action == "add-member" && !members.contains_key(&id);
+        let member = members.entry(id).or_insert_with(|| Member {
+            id,
+            ..Default::default()
+        });
+        member.update(data);
+        match action.as_str() {
+            "start-talking" => member.talking = true,
+            "stop-talking" => member.talking = false,
+            "mute-member" => member.speak = false,
+            "unmute-member" => member.speak = true,
+            "deaf-member" => member.hear = false,
+            "undeaf-member" => member.hear = true,
+            _ => {}
+        }
+        let member = member.clone();
+        Some(match joined {
+            true => RosterChange::Joined { conference, member },
+            false => RosterChange::Updated { conference, member },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::json_event;
+
+    fn event(mut body: serde_json::Value) -> Event {
+        body["Event-Name"] = "CUSTOM".into();
+        body["Event-Subclass"] = SUBCLASS.into();
+        body["Conference-Name"] = "3000".into();
+        json_event(body)
+    }
+
+    #[test]
+    fn list_members() {
+        let member = Member::from_list_line(
+            "7;sofia/internal/1000@10.0.0.1;7f4d;Alice;1000;hear|speak|talking|floor;0;1;300",
+        )
+        .unwrap();
+        assert_eq!(member.id, 7);
+        assert_eq!(member.uuid, "7f4d");
+        assert!(member.speak && member.hear && member.talking && member.floor);
+        assert!(!member.moderator);
+        assert_eq!(member.energy, Some(300));
+        assert!(Member::from_list_line("+OK").is_none());
+        assert_eq!(Members::NonModerator.to_string(), "non_moderator");
+    }
+
+    #[test]
+    fn follow_roster() {
+        let mut roster = Roster::new();
+        let change = roster.push(&event(serde_json::json!({"Action": "add-member", "Member-ID": "1", "Unique-ID": "a", "Speak": "true", "Hear": "true", "Member-Type": "moderator"})));
+        assert!(matches!(change, Some(RosterChange::Joined { .. })));
+        roster.push(&event(
+            serde_json::json!({"Action": "add-member", "Member-ID": "2"}),
+        ));
+        roster.push(&event(
+            serde_json::json!({"Action": "start-talking", "Member-ID": "2"}),
+        ));
+        roster.push(&event(
+            serde_json::json!({"Action": "mute-member", "Member-ID": "1"}),
+        ));
+        roster.push(&event(serde_json::json!({"Action": "floor-change", "Member-ID": "2", "Old-ID": "1", "New-ID": "2"})));
+        let talking: Vec<_> = roster.talking("3000").map(|member| member.id).collect();
+        assert_eq!(talking, [2]);
+        assert!(roster.member("3000", 2).unwrap().floor);
+        let first = roster.member("3000", 1).unwrap();
+        assert!(first.moderator && !first.speak && first.hear);
+
+        let change = roster.push(&event(
+            serde_json::json!({"Action": "del-member", "Member-ID": "1"}),
+        ));
+        assert!(matches!(change, Some(RosterChange::Left { member, .. }) if member.uuid == "a"));
+        assert_eq!(roster.members("3000").count(), 1);
+        roster.push(&event(serde_json::json!({"Action": "conference-destroy"})));
+        assert_eq!(roster.conferences().count(), 0);
+    }
+
+    #[test]
+    fn floor_and_unseen_members() {
+        let mut roster = Roster::new();
+        let change = roster.push(&event(
+            serde_json::json!({"Action": "stop-talking", "Member-ID": "4"}),
+        ));
+        assert!(matches!(change, Some(RosterChange::Updated { member, .. }) if member.id == 4));
+        roster.push(&event(serde_json::json!({"Action": "floor-change", "Member-ID": "4", "Old-ID": "none", "New-ID": "4"})));
+        assert!(roster.member("3000", 4).unwrap().floor);
+
+        // nobody has the floor, the event has no member
+        let change = roster.push(&event(
+            serde_json::json!({"Action": "floor-change", "Old-ID": "4", "New-ID": "none"}),
+        ));
+        assert!(
+            matches!(change, Some(RosterChange::Updated { member, .. }) if member.id == 4 && !member.floor)
+        );
+        assert!(!roster.member("3000", 4).unwrap().floor);
+    }
+}
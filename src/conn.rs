@@ -1,18 +1,37 @@
+use crate::codec::Frame;
+use crate::command::{command_line, terminate, Command, EventFormat};
 use crate::error::{EslError, Result};
 use crate::event::Event;
-use std::sync::Arc;
+use crate::reply::{api_result, reply_result, CommandReply};
+use crate::sequence::{SequenceGap, SequenceStats};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 use tokio::sync::{
+    broadcast,
     mpsc::{Receiver, Sender},
-    Mutex,
+    oneshot, Mutex,
 };
-use tracing::{info, error, warn};
-use tracing_subscriber::FmtSubscriber;
+use tracing::error;
+
+/// a terminated command and where to deliver its reply
+pub(crate) type Request = (String, oneshot::Sender<Result<Frame>>);
 
 #[derive(Debug, Clone)]
 pub struct Conn {
-    pub(crate) sender: Arc<Mutex<Sender<String>>>, // send command
+    pub(crate) sender: Arc<Mutex<Sender<Request>>>, // send command
     pub(crate) receiver: Arc<Mutex<Receiver<Result<Event>>>>, // receive freesiwtch event
     pub(crate) connected: Arc<Mutex<bool>>,
+    pub(crate) gaps: broadcast::Sender<SequenceGap>,
+    pub(crate) events: broadcast::Sender<Event>,
+    pub(crate) sequence_stats: Arc<Mutex<SequenceStats>>,
+    /// set once [`Conn::handle`] is reading events
+    pub(crate) handled: Arc<AtomicBool>,
+    pub(crate) timeout: Option<Duration>,
 }
 
 #[macro_export]
@@ -29,13 +48,23 @@ macro_rules! run {
 
 impl Conn {
     pub(crate) fn new(
-        sender: Arc<Mutex<Sender<String>>>,
+        sender: Arc<Mutex<Sender<Request>>>,
         receiver: Arc<Mutex<Receiver<Result<Event>>>>,
+        gaps: broadcast::Sender<SequenceGap>,
+        events: broadcast::Sender<Event>,
+        sequence_stats: Arc<Mutex<SequenceStats>>,
+        handled: Arc<AtomicBool>,
+        timeout: Option<Duration>,
     ) -> Self {
         Self {
             sender,
             receiver,
             connected: Arc::new(Mutex::new(true)),
+            gaps,
+            events,
+            sequence_stats,
+            handled,
+            timeout,
         }
     }
 
@@ -49,28 +78,112 @@ impl Conn {
         Err(EslError::ConnectionError(String::from("disconnected")))
     }
 
-    pub async fn send(&self, command: &str) -> Result<()> {
+    /// send a raw command and wait for its `command/reply` or `api/response`
+    ///
+    /// the command is written as it is, only a blank line, which would end it
+    /// early and start another one, is rejected. [`Self::execute`] checks each
+    /// argument of a [`Command`] instead.
+    ///
+    /// commands are pipelined: concurrent sends are written back to back and
+    /// each gets its own reply, in order. if the connection fails every
+    /// command still waiting gets the error
+    pub async fn send(&self, command: &str) -> Result<Frame> {
+        self.dispatch(command, terminate(command)?, self.timeout)
+            .await
+    }
+
+    /// like [`Self::send`], failing with [`EslError::Timeout`] when no reply
+    /// arrives in time. the reply still on its way is discarded
+    pub async fn send_timeout(&self, command: &str, timeout: Duration) -> Result<Frame> {
+        self.dispatch(command, terminate(command)?, Some(timeout))
+            .await
+    }
+
+    /// send a [`Command`], its arguments are checked before anything is written
+    pub async fn execute(&self, command: &Command) -> Result<Frame> {
+        let wire = command.encode()?;
+        let label = command_line(&wire).to_string();
+        self.dispatch(&label, wire, self.timeout).await
+    }
+
+    /// like [`Self::execute`], giving up after `timeout`
+    pub async fn execute_timeout(&self, command: &Command, timeout: Duration) -> Result<Frame> {
+        let wire = command.encode()?;
+        let label = command_line(&wire).to_string();
+        self.dispatch(&label, wire, Some(timeout)).await
+    }
+
+    /// resolves once the connection is gone, whatever ended it
+    pub async fn closed(&self) {
+        let sender = self.sender.lock().await.clone();
+        sender.closed().await
+    }
+
+    /// default timeout for commands sent through this handle, `None` waits forever
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+
+    async fn dispatch(
+        &self,
+        command: &str,
+        wire: String,
+        timeout: Option<Duration>,
+    ) -> Result<Frame> {
+        match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, self.request(wire))
+                .await
+                .map_err(|_| EslError::Timeout(command.to_string()))?,
+            None => self.request(wire).await,
+        }
+    }
+
+    /// queue an encoded command and wait for its reply
+    async fn request(&self, wire: String) -> Result<Frame> {
         self.is_connected().await?;
-        let sender = self.sender.clone();
-        let sender = sender.lock().await;
-        let command = format!("{}\n\n", command);
-        match sender.send(command).await {
-            Ok(_) => {}
-            Err(e) => {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        {
+            let sender = self.sender.lock().await;
+            if let Err(e) = sender.send((wire, reply_tx)).await {
                 error!("send command error: {}", e);
                 *self.connected.lock().await = false;
                 return Err(EslError::ConnectionError(String::from(
                     "send command error",
                 )));
             }
-        };
-        Ok(())
+        }
+        reply_rx.await.map_err(|_| {
+            EslError::ConnectionError(String::from("connection closed before reply"))
+        })?
+    }
+
+    /// gaps in `Event-Sequence`, requires [`crate::InboundOptions::track_sequence`]
+    pub fn sequence_gaps(&self) -> broadcast::Receiver<SequenceGap> {
+        self.gaps.subscribe()
+    }
+
+    /// every event from now on, next to [`Self::handle`]
+    ///
+    /// a receiver that falls more than 1000 events behind loses the oldest ones
+    pub fn events(&self) -> broadcast::Receiver<Event> {
+        self.events.subscribe()
+    }
+
+    /// sequence counters so far, all zero unless tracking is enabled, and the
+    /// events dropped before a handler was attached
+    pub async fn sequence_stats(&self) -> SequenceStats {
+        self.sequence_stats.lock().await.clone()
     }
 
     /// handle event
+    ///
+    /// the handler gets every event, a slow one holds up the connection. until
+    /// it is attached events wait in a queue of 1000, what finds it full is
+    /// dropped and counted in [`SequenceStats::dropped`]
     pub async fn handle(&mut self, hander: impl Fn(Event) + Send + Sync + 'static) {
         let receiver = self.receiver.clone();
         let connected = self.connected.clone();
+        self.handled.store(true, Ordering::SeqCst);
 
         tokio::spawn(async move {
             let mut receiver = receiver.lock().await;
@@ -85,40 +198,60 @@ impl Conn {
         });
     }
 
+    /// send a raw command answered by `command/reply`, `-ERR` replies are
+    /// errors. checked like [`Self::send`]
+    pub async fn command(&self, command: &str) -> Result<CommandReply> {
+        reply_result(self.send(command).await?)
+    }
+
     /// return custom job-uuid
     pub async fn bgapi(&mut self, command: &str) -> Result<String> {
         let uuid = uuid::Uuid::new_v4().to_string();
-        let command = format!("bgapi {}\njob-uuid:{}", command, uuid);
-        self.send(&command).await?;
-        return Ok(uuid);
+        let command = Command::bgapi(command, Some(uuid.clone()));
+        reply_result(self.execute(&command).await?)?;
+        Ok(uuid)
+    }
+
+    /// run an api command and return its output
+    pub async fn api(&mut self, command: &str) -> Result<String> {
+        api_result(self.execute(&Command::api(command)).await?)
     }
 
-    pub async fn api(&mut self, command: &str) -> Result<()> {
-        let command = format!("api {}", command);
-        self.send(&command).await
+    /// run an api command, giving up after `timeout`
+    pub async fn api_timeout(&mut self, command: &str, timeout: Duration) -> Result<String> {
+        api_result(
+            self.execute_timeout(&Command::api(command), timeout)
+                .await?,
+        )
     }
 
     /// subscribe events
     /// only support json format
     pub async fn subscribe(&mut self, events: &[&str]) -> Result<()> {
-        self.send(&format!("event json {}", events.join(" ")))
-            .await?;
+        let command = Command::event(EventFormat::Json, events.iter().copied());
+        reply_result(self.execute(&command).await?)?;
         Ok(())
     }
 
     pub async fn subscribe_all(&mut self) -> Result<()> {
-        self.send("event json all").await?;
+        reply_result(
+            self.execute(&Command::event(EventFormat::Json, ["all"]))
+                .await?,
+        )?;
         Ok(())
     }
 
     pub async fn unsubscribe(&mut self, events: &[&str]) -> Result<()> {
-        self.send(&format!("nixevent {}", events.join(" "))).await?;
+        reply_result(
+            self.execute(&Command::nixevent(events.iter().copied()))
+                .await?,
+        )?;
         Ok(())
     }
 
     /// unsubscribe all
     pub async fn unsubscribe_all(&mut self) -> Result<()> {
-        self.send("nixevent all").await?;
+        reply_result(self.execute(&Command::nixevent(["all"])).await?)?;
         Ok(())
     }
 }
@@ -0,0 +1,283 @@
+//! serde support for mapping events onto user types
+//!
+//! freeswitch sends every header as a string, so the deserializers here coerce
+//! `"42"` into integers, `"true"`/`"yes"`/`"on"`/`"1"` into booleans and so on.
+//! multi-value headers, either json arrays or `ARRAY::a|:b` strings, map onto
+//! sequences such as `Vec<String>`.
+//! field names are the raw header names, e.g. `#[serde(rename = "Unique-ID")]`.
+//! channel variables keep their `variable_` prefix in [`EventData::deserialize`]
+//! and lose it in [`EventData::deserialize_vars`], so a struct of plain
+//! `snake_case` fields maps straight onto the channel variables.
+//!
+//! [`EventData::deserialize`]: crate::event::EventData::deserialize
+//! [`EventData::deserialize_vars`]: crate::event::EventData::deserialize_vars
+
+use serde::de::{
+    self,
+    value::{Error, MapDeserializer, SeqDeserializer},
+    DeserializeOwned, IntoDeserializer, Visitor,
+};
+use serde_json::Value;
+
+use crate::error::{EslError, Result};
+
+/// prefix freeswitch uses when a multi-value header is flattened to a string
+pub(crate) const ARRAY_PREFIX: &str = "ARRAY::";
+/// separator between the items of an `ARRAY::` string
+pub(crate) const ARRAY_SEPARATOR: &str = "|:";
+
+/// deserialize `T` from `(key, value)` pairs of header values
+pub(crate) fn from_fields<'a, T, I>(fields: I) -> Result<T>
+where
+    T: DeserializeOwned,
+    I: IntoIterator<Item = (&'a str, FieldValue<'a>)>,
+{
+    let de = MapDeserializer::new(fields.into_iter());
+    T::deserialize(de).map_err(|e| EslError::DeserializeError(e.to_string()))
+}
+
+/// parse freeswitch's boolean spellings, as `switch_true` does
+pub fn parse_bool(v: &str) -> Option<bool> {
+    match v.trim().to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" | "enabled" | "active" | "allow" | "1" => Some(true),
+        "false" | "no" | "off" | "disabled" | "inactive" | "deny" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// split an `ARRAY::a|:b` encoded value into its items
+pub(crate) fn split_array(v: &str) -> Option<impl Iterator<Item = &str>> {
+    v.strip_prefix(ARRAY_PREFIX)
+        .map(|items| items.split(ARRAY_SEPARATOR))
+}
+
+/// a single header value that coerces itself to whatever type is requested
+#[derive(Clone, Copy)]
+pub(crate) enum FieldValue<'a> {
+    Str(&'a str),
+    Json(&'a Value),
+}
+
+impl<'a> FieldValue<'a> {
+    fn as_str(self) -> Option<&'a str> {
+        match self {
+            FieldValue::Str(s) => Some(s),
+            FieldValue::Json(Value::String(s)) => Some(s),
+            FieldValue::Json(_) => None,
+        }
+    }
+}
+
+impl<'a> IntoDeserializer<'a, Error> for FieldValue<'a> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident),* $(,)?) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> std::result::Result<V::Value, Error> {
+                match self.as_str() {
+                    Some(s) => match s.trim().parse() {
+                        Ok(v) => visitor.$visit(v),
+                        Err(_) => Err(de::Error::invalid_value(de::Unexpected::Str(s), &visitor)),
+                    },
+                    None => self.deserialize_any(visitor),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for FieldValue<'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> std::result::Result<V::Value, Error> {
+        match self {
+            FieldValue::Str(s) => visitor.visit_borrowed_str(s),
+            FieldValue::Json(value) => match value {
+                Value::Null => visitor.visit_unit(),
+                Value::Bool(b) => visitor.visit_bool(*b),
+                Value::Number(n) => {
+                    if let Some(n) = n.as_u64() {
+                        visitor.visit_u64(n)
+                    } else if let Some(n) = n.as_i64() {
+                        visitor.visit_i64(n)
+                    } else {
+                        visitor.visit_f64(n.as_f64().unwrap_or_default())
+                    }
+                }
+                Value::String(s) => visitor.visit_borrowed_str(s),
+                Value::Array(items) => {
+                    visitor.visit_seq(SeqDeserializer::new(items.iter().map(FieldValue::Json)))
+                }
+                Value::Object(map) => visitor.visit_map(MapDeserializer::new(
+                    map.iter().map(|(k, v)| (k.as_str(), FieldValue::Json(v))),
+                )),
+            },
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> std::result::Result<V::Value, Error> {
+        match self.as_str() {
+            Some(s) => match parse_bool(s) {
+                Some(v) => visitor.visit_bool(v),
+                None => Err(de::Error::invalid_value(de::Unexpected::Str(s), &visitor)),
+            },
+            None => self.deserialize_any(visitor),
+        }
+    }
+
+    deserialize_parsed! {
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_i128 => visit_i128,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_u128 => visit_u128,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    /// an empty or null header is treated as absent
+    fn deserialize_option<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> std::result::Result<V::Value, Error> {
+        match self {
+            FieldValue::Json(Value::Null) => visitor.visit_none(),
+            _ if self.as_str() == Some("") => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    /// a plain string is a list of one, unless it is `ARRAY::` encoded
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> std::result::Result<V::Value, Error> {
+        match self.as_str() {
+            Some(s) => match split_array(s) {
+                Some(items) => visitor.visit_seq(SeqDeserializer::new(items.map(FieldValue::Str))),
+                None => visitor.visit_seq(SeqDeserializer::new(std::iter::once(self))),
+            },
+            None => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> std::result::Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> std::result::Result<V::Value, Error> {
+        match self.as_str() {
+            Some(s) => visitor.visit_enum(s.into_deserializer()),
+            None => Err(de::Error::custom("expected a string for an enum")),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        str string bytes byte_buf unit unit_struct tuple
+        tuple_struct map struct identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    enum Direction {
+        #[serde(rename = "inbound")]
+        Inbound,
+        #[serde(rename = "outbound")]
+        Outbound,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Channel {
+        #[serde(rename = "Unique-ID")]
+        uuid: String,
+        #[serde(rename = "Event-Sequence")]
+        sequence: u64,
+        #[serde(rename = "Call-Direction")]
+        direction: Direction,
+        #[serde(rename = "variable_answered")]
+        answered: bool,
+        #[serde(rename = "variable_missing", default)]
+        missing: Option<u32>,
+        #[serde(rename = "variable_empty")]
+        empty: Option<u32>,
+    }
+
+    #[test]
+    fn coerce_strings() {
+        let channel: Channel = from_fields([
+            ("Unique-ID", FieldValue::Str("abc")),
+            ("Event-Sequence", FieldValue::Str(" 1234 ")),
+            ("Call-Direction", FieldValue::Str("outbound")),
+            ("variable_answered", FieldValue::Str("yes")),
+            ("variable_empty", FieldValue::Str("")),
+            ("Other", FieldValue::Str("ignored")),
+        ])
+        .unwrap();
+        assert_eq!(channel.uuid, "abc");
+        assert_eq!(channel.sequence, 1234);
+        assert_eq!(channel.direction, Direction::Outbound);
+        assert!(channel.answered);
+        assert_eq!(channel.missing, None);
+        assert_eq!(channel.empty, None);
+    }
+
+    #[test]
+    fn reject_bad_numbers() {
+        let err = from_fields::<Channel, _>([
+            ("Unique-ID", FieldValue::Str("abc")),
+            ("Event-Sequence", FieldValue::Str("x")),
+            ("Call-Direction", FieldValue::Str("inbound")),
+            ("variable_answered", FieldValue::Str("false")),
+        ])
+        .unwrap_err();
+        assert!(matches!(err, EslError::DeserializeError(_)));
+    }
+
+    #[test]
+    fn multi_value_headers() {
+        #[derive(Deserialize)]
+        struct Codecs {
+            json: Vec<String>,
+            encoded: Vec<String>,
+            single: Vec<u16>,
+            ports: Vec<u16>,
+        }
+
+        let json = serde_json::json!(["PCMU", "PCMA"]);
+        let ports = serde_json::json!(["5060", 5080]);
+        let codecs: Codecs = from_fields([
+            ("json", FieldValue::Json(&json)),
+            ("encoded", FieldValue::Str("ARRAY::OPUS|:G722")),
+            ("single", FieldValue::Str("8000")),
+            ("ports", FieldValue::Json(&ports)),
+        ])
+        .unwrap();
+        assert_eq!(codecs.json, ["PCMU", "PCMA"]);
+        assert_eq!(codecs.encoded, ["OPUS", "G722"]);
+        assert_eq!(codecs.single, [8000]);
+        assert_eq!(codecs.ports, [5060, 5080]);
+    }
+}
@@ -0,0 +1,277 @@
+//! DTMF digits of one channel
+//!
+//! [`Channel::dtmf`] follows DTMF events for the channel. digits detected in
+//! the audio (with `start_dtmf` running), sent as RFC 2833 events or as SIP
+//! INFO all come out as the same [`Dtmf`], only [`Dtmf::source`] differs.
+//!
+//! ```no_run
+//! # async fn demo(conn: esl_rs::conn::Conn) -> esl_rs::error::Result<()> {
+//! use std::time::Duration;
+//!
+//! let mut dtmf = conn.channel("7f4d")?.dtmf().await?;
+//! let pin = dtmf
+//!     .collect_digits(4, "#", Duration::from_secs(10), Duration::from_secs(3))
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    pin::Pin,
+    sync::Arc,
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+
+use futures::{Stream, StreamExt};
+use tokio::{sync::broadcast, time::timeout};
+use tokio_util::sync::ReusableBoxFuture;
+use tracing::warn;
+
+use crate::{
+    channel::Channel,
+    command::{Command, EventFormat},
+    error::{EslError, Result},
+    event::{Event, EventData},
+    hangup::HangupCause,
+    reply::reply_result,
+};
+
+const DTMF_EVENTS: [&str; 2] = ["DTMF", "CHANNEL_HANGUP"];
+
+/// where a digit was detected, `DTMF-Source`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DtmfSource {
+    /// RFC 2833 telephone events
+    Rtp,
+    /// tones in the audio, see the `start_dtmf` application
+    InbandAudio,
+    /// signalled by the endpoint, e.g. SIP INFO
+    Endpoint,
+    /// queued by an application
+    App,
+    Other(String),
+}
+
+impl DtmfSource {
+    fn parse(source: &str) -> Self {
+        match source {
+            "RTP" => Self::Rtp,
+            "INBAND_AUDIO" => Self::InbandAudio,
+            "ENDPOINT" => Self::Endpoint,
+            "APP" => Self::App,
+            other => Self::Other(other.to_string()),
+        }
+    }
+}
+
+/// one digit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dtmf {
+    pub digit: char,
+    pub duration: Duration,
+    pub source: DtmfSource,
+}
+
+impl Dtmf {
+    /// from a DTMF event, `None` for other events
+    pub fn from_event(data: &EventData) -> Option<Self> {
+        let digit = data.get_body_by_key("DTMF-Digit")?.chars().next()?;
+        // in samples at 8kHz
+        let samples = data
+            .get_body_by_key("DTMF-Duration")
+            .and_then(|samples| samples.trim().parse::<u64>().ok())
+            .unwrap_or_default();
+        let source = data
+            .get_body_by_key("DTMF-Source")
+            .map_or(DtmfSource::Other(String::new()), |source| {
+                DtmfSource::parse(&source)
+            });
+        Some(Self {
+            digit,
+            duration: Duration::from_micros(samples * 125),
+            source,
+        })
+    }
+}
+
+/// digits of one channel as they come, see [`Channel::dtmf`]
+///
+/// a [`Stream`] of digits. it ends after yielding [`EslError::Hangup`] once the
+/// channel hangs up, or the error that closed the connection
+#[derive(Debug)]
+pub struct DtmfStream {
+    next: ReusableBoxFuture<'static, (Result<Dtmf>, Arc<str>, broadcast::Receiver<Event>)>,
+    /// what ended the stream
+    ended: Option<EslError>,
+}
+
+/// wait for the next digit of `uuid`, handing the receiver back
+async fn next_dtmf(
+    uuid: Arc<str>,
+    mut events: broadcast::Receiver<Event>,
+) -> (Result<Dtmf>, Arc<str>, broadcast::Receiver<Event>) {
+    loop {
+        let evt = match events.recv().await {
+            Ok(evt) => evt,
+            Err(broadcast::error::RecvError::Lagged(n)) => {
+                warn!("dtmf of {} missed {} events", uuid, n);
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => {
+                let err = EslError::ConnectionError("connection closed".to_string());
+                return (Err(err), uuid, events);
+            }
+        };
+        if evt.get_body_by_key("Unique-ID").as_deref() != Some(&*uuid) {
+            continue;
+        }
+        match evt {
+            Event::Dtmf(data) => match Dtmf::from_event(&data) {
+                Some(dtmf) => return (Ok(dtmf), uuid, events),
+                None => warn!("dtmf event without a digit"),
+            },
+            Event::ChannelHangup(data) => {
+                let cause = data.hangup_cause().unwrap_or(HangupCause::NormalClearing);
+                return (Err(EslError::Hangup(cause)), uuid, events);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Stream for DtmfStream {
+    type Item = Result<Dtmf>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.ended.is_some() {
+            return Poll::Ready(None);
+        }
+        let (res, uuid, events) = ready!(self.next.poll(cx));
+        match &res {
+            Ok(_) => self.next.set(next_dtmf(uuid, events)),
+            Err(e) => self.ended = Some(e.clone()),
+        }
+        Poll::Ready(Some(res))
+    }
+}
+
+impl DtmfStream {
+    fn new(uuid: &str, events: broadcast::Receiver<Event>) -> Self {
+        Self {
+            next: ReusableBoxFuture::new(next_dtmf(uuid.into(), events)),
+            ended: None,
+        }
+    }
+
+    /// collect up to `max` digits
+    ///
+    /// stops at one of `terminators`, which is left out, after `first_timeout`
+    /// without a first digit or `inter_timeout` between digits. fails with
+    /// [`EslError::NoInput`] when nothing was entered
+    pub async fn collect_digits(
+        &mut self,
+        max: usize,
+        terminators: &str,
+        first_timeout: Duration,
+        inter_timeout: Duration,
+    ) -> Result<String> {
+        let mut digits = String::new();
+        while digits.len() < max {
+            let wait = match digits.is_empty() {
+                true => first_timeout,
+                false => inter_timeout,
+            };
+            let Ok(dtmf) = timeout(wait, self.next()).await else {
+                break;
+            };
+            let Some(dtmf) = dtmf else {
+                return Err(self.ended.clone().unwrap_or(EslError::NoInput));
+            };
+            let dtmf = dtmf?;
+            if terminators.contains(dtmf.digit) {
+                break;
+            }
+            digits.push(dtmf.digit);
+        }
+        match digits.is_empty() {
+            true => Err(EslError::NoInput),
+            false => Ok(digits),
+        }
+    }
+}
+
+impl Channel {
+    /// follow the digits pressed on this channel from now on, DTMF is added
+    /// to the subscription
+    pub async fn dtmf(&self) -> Result<DtmfStream> {
+        let conn = self.conn();
+        let events = conn.events();
+        reply_result(
+            conn.execute(&Command::event(EventFormat::Json, DTMF_EVENTS))
+                .await?,
+        )?;
+        Ok(DtmfStream::new(self.uuid(), events))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::json_event;
+
+    fn dtmf(uuid: &str, digit: &str, source: &str) -> Event {
+        let body = serde_json::json!({
+            "Event-Name": "DTMF",
+            "Unique-ID": uuid,
+            "DTMF-Digit": digit,
+            "DTMF-Duration": "2000",
+            "DTMF-Source": source,
+        });
+        json_event(body)
+    }
+
+    #[tokio::test]
+    async fn collect_digits() {
+        let (tx, _) = broadcast::channel(16);
+        let mut stream = DtmfStream::new("7f4d", tx.subscribe());
+        tx.send(dtmf("7f4d", "1", "RTP")).unwrap();
+        tx.send(dtmf("other", "9", "RTP")).unwrap();
+        tx.send(dtmf("7f4d", "2", "INBAND_AUDIO")).unwrap();
+        tx.send(dtmf("7f4d", "#", "RTP")).unwrap();
+        tx.send(dtmf("7f4d", "3", "ENDPOINT")).unwrap();
+
+        let first = stream.next().await.unwrap().unwrap();
+        assert_eq!(first.digit, '1');
+        assert_eq!(first.duration, Duration::from_millis(250));
+        assert_eq!(first.source, DtmfSource::Rtp);
+        let digits = stream
+            .collect_digits(4, "#", Duration::from_secs(1), Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(digits, "2");
+
+        // max digits, then the inter digit timeout with nothing pending
+        let digits = stream
+            .collect_digits(1, "#", Duration::from_secs(1), Duration::from_secs(1))
+            .await
+            .unwrap();
+        assert_eq!(digits, "3");
+        let none = stream
+            .collect_digits(4, "#", Duration::from_millis(10), Duration::from_millis(10))
+            .await;
+        assert_eq!(none, Err(EslError::NoInput));
+
+        // the hangup ends the stream
+        tx.send(json_event(serde_json::json!({"Event-Name": "CHANNEL_HANGUP", "Unique-ID": "7f4d", "Hangup-Cause": "USER_BUSY"})))
+            .unwrap();
+        tx.send(dtmf("7f4d", "4", "RTP")).unwrap();
+        let hangup = EslError::Hangup(HangupCause::UserBusy);
+        assert_eq!(stream.next().await, Some(Err(hangup.clone())));
+        assert_eq!(stream.next().await, None);
+        let digits = stream
+            .collect_digits(4, "#", Duration::from_secs(1), Duration::from_secs(1))
+            .await;
+        assert_eq!(digits, Err(hangup));
+    }
+}
@@ -2,6 +2,8 @@ use std::num::ParseIntError;
 
 use thiserror::Error;
 
+use crate::hangup::HangupCause;
+
 #[derive(Clone, Debug, PartialEq, Ord, PartialOrd, Eq, Hash, Error)]
 pub enum EslError {
     #[error("unknown error")]
@@ -22,11 +24,41 @@ pub enum EslError {
     #[error("{0:?}")]
     ApiError(String),
 
+    #[error("no such channel")]
+    NoSuchChannel,
+
+    #[error("invalid command: {0}")]
+    InvalidCommand(String),
+
+    #[error("permission denied")]
+    PermissionDenied,
+
+    #[error("usage: {0}")]
+    Usage(String),
+
     #[error("")]
     CodeParseError(),
 
     #[error("Didnt get any digits")]
     NoInput,
+
+    #[error("invalid input: {0:?}")]
+    InvalidInput(String),
+
+    #[error("deserialize error: {0}")]
+    DeserializeError(String),
+
+    #[error("protocol error: {0}")]
+    ProtocolError(String),
+
+    #[error("invalid argument: {0}")]
+    InvalidArgument(String),
+
+    #[error("hangup: {0}")]
+    Hangup(HangupCause),
+
+    #[error("timeout waiting for reply to {0:?}")]
+    Timeout(String),
 }
 
 pub type Result<T> = std::result::Result<T, EslError>;
@@ -41,7 +73,9 @@ impl From<tokio::sync::mpsc::error::TryRecvError> for EslError {
     fn from(error: tokio::sync::mpsc::error::TryRecvError) -> Self {
         match error {
             tokio::sync::mpsc::error::TryRecvError::Empty => Self::EmptyEvent,
-            tokio::sync::mpsc::error::TryRecvError::Disconnected => Self::ConnectionError(error.to_string()),
+            tokio::sync::mpsc::error::TryRecvError::Disconnected => {
+                Self::ConnectionError(error.to_string())
+            }
         }
     }
 }
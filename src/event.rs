@@ -1,44 +1,11 @@
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::Value;
-use std::{collections::HashMap, ops::Deref, os::raw};
+use std::{collections::HashMap, ops::Deref};
 
-pub(crate) fn get_header_end(s: &[u8]) -> Option<usize> {
-    let mut i = 0;
-    let mut last = 0;
-    for c in s {
-        if *c == b'\n' {
-            if last == b'\n' {
-                return Some(i + 1);
-            }
-            last = *c;
-        } else {
-            last = *c;
-        }
-        i += 1;
-    }
-    None
-}
-
-pub(crate) fn parse_header(header: &[u8]) -> HashMap<String, String> {
-    let mut map = HashMap::new();
-    let mut key = String::new();
-    let mut value = String::new();
-    let mut is_key = true;
-    for c in header {
-        if *c == b':' {
-            is_key = false;
-        } else if *c == b'\n' {
-            map.insert(key, value);
-            key = String::new();
-            value = String::new();
-            is_key = true;
-        } else if is_key {
-            key.push(*c as char);
-        } else {
-            value.push(*c as char);
-        }
-    }
-    map
-}
+use crate::{
+    de::{split_array, FieldValue, ARRAY_PREFIX, ARRAY_SEPARATOR},
+    error::Result,
+};
 
 #[derive(Debug, Clone, strum::Display)]
 pub enum Event {
@@ -87,6 +54,8 @@ pub enum Event {
     ChannelUnhold(EventData),
     Confirmed(EventData),
     ConferenceSendPresence(EventData),
+    BackgroundJob(EventData),
+    Message(EventData),
     Unknown(EventData),
 }
 
@@ -140,14 +109,29 @@ impl Deref for Event {
             | Event::ChannelUnhold(data)
             | Event::Confirmed(data)
             | Event::ConferenceSendPresence(data)
+            | Event::BackgroundJob(data)
+            | Event::Message(data)
             | Event::Unknown(data) => data,
         }
     }
 }
 
+/// events serialize as their `EventData`, the variant is implied by `Event-Name`
+impl Serialize for Event {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        self.deref().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Event {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        <EventData as Deserialize>::deserialize(deserializer).map(Into::into)
+    }
+}
+
 impl From<EventData> for Event {
     fn from(value: EventData) -> Self {
-        match value.get_event_name().as_ref().map(|s| s.as_str()) {
+        match value.get_event_name().as_deref() {
             Some("API") => Self::Api(value),
             Some("HEARTBEAT") => Self::Heartbeat(value),
             Some("RECV_RTCP_MESSAGE") => Self::RecvRtcpMessage(value),
@@ -193,38 +177,49 @@ impl From<EventData> for Event {
             Some("CHANNEL_UNHOLD") => Self::ChannelUnhold(value),
             Some("CONFIRMED") => Self::Confirmed(value),
             Some("CONFERENCE_SEND_PRESENCE") => Self::ConferenceSendPresence(value),
+            Some("BACKGROUND_JOB") => Self::BackgroundJob(value),
+            Some("MESSAGE") => Self::Message(value),
             _ => Self::Unknown(value), // 处理未知事件
         }
     }
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct EventData {
     pub headers: HashMap<String, String>,
     pub raw_body: Option<String>,
-    pub body: Option<HashMap<String, String>>,
+    /// the json event, kept as sent: multi-value headers are arrays
+    pub body: Option<HashMap<String, Value>>,
+}
+
+/// render a body value the way freeswitch renders it in plain events
+fn value_to_string(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(s) => Some(s.to_string()),
+        Value::Array(items) => Some(format!(
+            "{}{}",
+            ARRAY_PREFIX,
+            items
+                .iter()
+                .filter_map(value_to_string)
+                .collect::<Vec<_>>()
+                .join(ARRAY_SEPARATOR)
+        )),
+        v => Some(v.to_string()),
+    }
 }
 
 impl EventData {
     pub fn new(headers: HashMap<String, String>, raw_body: Option<String>) -> Self {
-        // 如果有body，则json反序列化解析body
-        let body = if let Some(body) = raw_body.clone() {
-            if let Ok(body) = serde_json::from_str::<Value>(&body) {
-                let mut map = HashMap::new();
-                if let Value::Object(body) = body {
-                    for (k, v) in body {
-                        if let Value::String(v) = v {
-                            map.insert(k, v);
-                        }
-                    }
-                }
-                Some(map)
-            } else {
-                None
-            }
-        } else {
-            None
-        };
+        // 只有事件json才解析body，api/response 等保持原样
+        let is_event_json = headers
+            .get("Content-Type")
+            .is_none_or(|t| t.trim() == "text/event-json");
+        let body = raw_body
+            .as_deref()
+            .filter(|_| is_event_json)
+            .and_then(|body| serde_json::from_str::<HashMap<String, Value>>(body).ok());
         Self {
             headers,
             raw_body: if body.is_none() { raw_body } else { None },
@@ -237,23 +232,51 @@ impl EventData {
     }
 
     pub fn get_job_uuid(&self) -> Option<String> {
-        self.get_header(&"Job-UUID").map(|s| s.to_string())
+        self.get_header("Job-UUID").map(|s| s.to_string())
     }
 
-    pub fn get_body(&self) -> Option<&HashMap<String, String>> {
+    pub fn get_body(&self) -> Option<&HashMap<String, Value>> {
         self.body.as_ref()
     }
 
+    /// multi-value headers come back `ARRAY::a|:b` encoded, see [`Self::get_body_list`]
     pub fn get_body_by_key(&self, key: &str) -> Option<String> {
         self.get_body()
             .and_then(|v| v.get(key))
-            .map(|s| s.to_string())
+            .and_then(value_to_string)
+    }
+
+    /// a body field as a list, whether it was sent as an array,
+    /// an `ARRAY::` encoded string or a single value
+    pub fn get_body_list(&self, key: &str) -> Option<Vec<String>> {
+        match self.get_body()?.get(key)? {
+            Value::Array(items) => Some(items.iter().filter_map(value_to_string).collect()),
+            Value::String(s) => Some(match split_array(s) {
+                Some(items) => items.map(|s| s.to_string()).collect(),
+                None => vec![s.to_string()],
+            }),
+            v => value_to_string(v).map(|s| vec![s]),
+        }
+    }
+
+    /// the embedded `_body` of the event, e.g. BACKGROUND_JOB output or MESSAGE text,
+    /// or the raw body of non-event replies such as `api/response`
+    pub fn content(&self) -> Option<&str> {
+        match self.get_body() {
+            Some(body) => body.get("_body").and_then(Value::as_str),
+            None => self.raw_body.as_deref(),
+        }
     }
 
     pub fn get_event_name(&self) -> Option<String> {
         self.get_body_by_key("Event-Name")
     }
 
+    /// `Event-Subclass` of CUSTOM events, e.g. `conference::maniacal`
+    pub fn get_subclass(&self) -> Option<String> {
+        self.get_body_by_key("Event-Subclass")
+    }
+
     pub fn get_channel_call_uuid(&self) -> Option<String> {
         self.get_body_by_key("Channel-Call-UUID")
     }
@@ -261,4 +284,114 @@ impl EventData {
     pub fn get_var(&self, key: &str) -> Option<String> {
         self.get_body_by_key(&format!("variable_{}", key))
     }
+
+    /// map headers and body onto `T`, see [`crate::de`] for the coercion rules
+    ///
+    /// body fields take precedence over socket headers with the same name
+    pub fn deserialize<T: DeserializeOwned>(&self) -> Result<T> {
+        let mut fields: HashMap<&str, FieldValue> = self
+            .headers
+            .iter()
+            .map(|(k, v)| (k.as_str(), FieldValue::Str(v.trim())))
+            .collect();
+        if let Some(body) = &self.body {
+            fields.extend(body.iter().map(|(k, v)| (k.as_str(), FieldValue::Json(v))));
+        }
+        crate::de::from_fields(fields)
+    }
+
+    /// map the `variable_*` fields onto `T`, with the prefix stripped
+    pub fn deserialize_vars<T: DeserializeOwned>(&self) -> Result<T> {
+        let vars = self.body.iter().flatten().filter_map(|(k, v)| {
+            k.strip_prefix("variable_")
+                .map(|k| (k, FieldValue::Json(v)))
+        });
+        crate::de::from_fields(vars)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel_answer() -> EventData {
+        let headers = HashMap::from([
+            ("Content-Type".to_string(), "text/event-json".to_string()),
+            ("Content-Length".to_string(), "0".to_string()),
+        ]);
+        let body = r#"{"Event-Name":"CHANNEL_ANSWER","Unique-ID":"abc","Event-Sequence":"42","variable_sip_call_id":"xyz","variable_answersec":"3"}"#;
+        EventData::new(headers, Some(body.to_string()))
+    }
+
+    #[test]
+    fn deserialize_event() {
+        #[derive(Deserialize)]
+        struct Answer {
+            #[serde(rename = "Unique-ID")]
+            uuid: String,
+            #[serde(rename = "Event-Sequence")]
+            sequence: u64,
+            #[serde(rename = "Content-Type")]
+            content_type: String,
+            #[serde(rename = "variable_sip_call_id")]
+            call_id: String,
+        }
+
+        #[derive(Deserialize)]
+        struct Vars {
+            sip_call_id: String,
+            answersec: u32,
+        }
+
+        let data = channel_answer();
+        let answer: Answer = data.deserialize().unwrap();
+        assert_eq!(answer.uuid, "abc");
+        assert_eq!(answer.sequence, 42);
+        assert_eq!(answer.content_type, "text/event-json");
+        assert_eq!(answer.call_id, "xyz");
+
+        let vars: Vars = data.deserialize_vars().unwrap();
+        assert_eq!(vars.sip_call_id, "xyz");
+        assert_eq!(vars.answersec, 3);
+    }
+
+    #[test]
+    fn serialize_roundtrip() {
+        let evt: Event = channel_answer().into();
+        let json = serde_json::to_string(&evt).unwrap();
+        let evt: Event = serde_json::from_str(&json).unwrap();
+        assert!(matches!(evt, Event::ChannelAnswer(_)));
+        assert_eq!(evt.get_var("sip_call_id").as_deref(), Some("xyz"));
+    }
+
+    #[test]
+    fn lossless_body() {
+        let headers = HashMap::from([("Content-Type".to_string(), " text/event-json".to_string())]);
+        let body = r#"{"Event-Name":"BACKGROUND_JOB","Job-UUID":"j1","Event-Sequence":7,"variable_rtp_codecs":["PCMU","PCMA"],"variable_encoded":"ARRAY::a|:b","_body":"+OK 1234\n"}"#;
+        let evt: Event = EventData::new(headers, Some(body.to_string())).into();
+        assert!(matches!(evt, Event::BackgroundJob(_)));
+        assert_eq!(evt.content(), Some("+OK 1234\n"));
+        assert_eq!(evt.get_body_by_key("Event-Sequence").as_deref(), Some("7"));
+        assert_eq!(
+            evt.get_var("rtp_codecs").as_deref(),
+            Some("ARRAY::PCMU|:PCMA")
+        );
+        assert_eq!(
+            evt.get_body_list("variable_rtp_codecs"),
+            Some(vec!["PCMU".to_string(), "PCMA".to_string()])
+        );
+        assert_eq!(
+            evt.get_body_list("variable_encoded"),
+            Some(vec!["a".to_string(), "b".to_string()])
+        );
+    }
+
+    #[test]
+    fn api_response_is_not_an_event() {
+        let headers = HashMap::from([("Content-Type".to_string(), " api/response".to_string())]);
+        let body = r#"{"row_count":0}"#;
+        let data = EventData::new(headers, Some(body.to_string()));
+        assert!(data.body.is_none());
+        assert_eq!(data.content(), Some(body));
+    }
 }
@@ -0,0 +1,354 @@
+//! mod_fifo: `fifo count` / `fifo list` and `fifo::info` events
+//!
+//! [`FifoTracker`] follows the events, subscribe them with [`FIFO_EVENTS`],
+//! and keeps the callers waiting and the consumers of every fifo.
+//!
+//! ```no_run
+//! # async fn demo(conn: esl_rs::conn::Conn) -> esl_rs::error::Result<()> {
+//! for fifo in conn.fifo_count(None).await? {
+//!     println!("{}: {} waiting, {} consumers", fifo.name, fifo.callers, fifo.consumers);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::{
+    channel::token,
+    command::Command,
+    conn::Conn,
+    error::{EslError, Result},
+    event::Event,
+    reply::api_result,
+    xml::elements,
+};
+
+/// what to subscribe for a [`FifoTracker`]
+pub const FIFO_EVENTS: [&str; 2] = ["CUSTOM", "fifo::info"];
+
+const SUBCLASS: &str = "fifo::info";
+
+/// a line of `fifo count`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FifoCount {
+    pub name: String,
+    pub consumers: u32,
+    pub callers: u32,
+    /// outbound members
+    pub members: u32,
+    pub ring_consumers: u32,
+    pub idle_consumers: u32,
+}
+
+impl FifoCount {
+    /// `name:consumer_count:caller_count:member_count:ring_consumer_count:idle_consumers`
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.trim().rsplitn(6, ':');
+        let mut number = || fields.next()?.trim().parse().ok();
+        let (idle_consumers, ring_consumers, members, callers, consumers) =
+            (number()?, number()?, number()?, number()?, number()?);
+        Some(Self {
+            name: fields.next()?.to_string(),
+            consumers,
+            callers,
+            members,
+            ring_consumers,
+            idle_consumers,
+        })
+    }
+}
+
+/// a caller waiting in a fifo, from `fifo list`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FifoCaller {
+    pub uuid: String,
+    pub status: String,
+    pub caller_id_name: String,
+    pub caller_id_number: String,
+    /// when it joined, as freeswitch formats it
+    pub timestamp: String,
+}
+
+/// a fifo from `fifo list`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FifoInfo {
+    pub name: String,
+    pub consumer_count: u32,
+    pub caller_count: u32,
+    pub waiting_count: u32,
+    pub importance: u32,
+    pub callers: Vec<FifoCaller>,
+    /// uuids of the consumers
+    pub consumers: Vec<String>,
+}
+
+/// parse the xml of `fifo list`
+pub fn parse_fifo_list(xml: &str) -> Vec<FifoInfo> {
+    let mut fifos: Vec<FifoInfo> = Vec::new();
+    // closing tags are not reported, the section of the fifo the last one opened
+    let mut section = String::new();
+    for element in elements(xml) {
+        if element.name == "fifo" {
+            section.clear();
+            fifos.push(FifoInfo {
+                name: element.attr("name"),
+                consumer_count: element.attr_as("consumer_count").unwrap_or_default(),
+                caller_count: element.attr_as("caller_count").unwrap_or_default(),
+                waiting_count: element.attr_as("waiting_count").unwrap_or_default(),
+                importance: element.attr_as("importance").unwrap_or_default(),
+                ..Default::default()
+            });
+            continue;
+        }
+        let Some(fifo) = fifos.last_mut() else {
+            continue;
+        };
+        match (element.name.as_str(), section.as_str()) {
+            ("outbound" | "callers" | "consumers" | "bridges", _) => section = element.name,
+            // `bridges` has a `caller` and a `consumer` per bridge too
+            ("caller", "callers") => fifo.callers.push(FifoCaller {
+                uuid: element.attr("uuid"),
+                status: element.attr("status"),
+                caller_id_name: element.attr("caller_id_name"),
+                caller_id_number: element.attr("caller_id_number"),
+                timestamp: element.attr("timestamp"),
+            }),
+            ("consumer", "consumers") => fifo.consumers.push(element.attr("uuid")),
+            _ => {}
+        }
+    }
+    fifos
+}
+
+impl Conn {
+    /// `fifo count`, of every fifo or just `name`
+    pub async fn fifo_count(&self, name: Option<&str>) -> Result<Vec<FifoCount>> {
+        let text = self.fifo_api("count", name).await?;
+        Ok(text.lines().filter_map(FifoCount::parse).collect())
+    }
+
+    /// `fifo list`, of every fifo or just `name`
+    pub async fn fifo_list(&self, name: Option<&str>) -> Result<Vec<FifoInfo>> {
+        Ok(parse_fifo_list(&self.fifo_api("list", name).await?))
+    }
+
+    async fn fifo_api(&self, command: &str, name: Option<&str>) -> Result<String> {
+        let line = match name {
+            Some(name) => format!("fifo {} {}", command, token(name)?),
+            None => format!("fifo {}", command),
+        };
+        let text = api_result(self.execute(&Command::api(line)).await?)?;
+        if text.trim_start().starts_with("invalid fifo") {
+            return Err(EslError::ApiError(text.trim().to_string()));
+        }
+        Ok(text)
+    }
+}
+
+/// `FIFO-Action` of a `fifo::info` event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FifoAction {
+    /// a caller joined
+    Push,
+    /// a caller left before being served
+    Abort,
+    CallerPop,
+    ConsumerPop,
+    ConsumerStart,
+    ConsumerStop,
+    BridgeCallerStart,
+    BridgeCallerStop,
+    BridgeConsumerStart,
+    BridgeConsumerStop,
+    Other(String),
+}
+
+impl FifoAction {
+    fn parse(action: &str) -> Self {
+        match action {
+            "push" => Self::Push,
+            "abort" => Self::Abort,
+            "caller_pop" => Self::CallerPop,
+            "consumer_pop" => Self::ConsumerPop,
+            "consumer_start" => Self::ConsumerStart,
+            "consumer_stop" => Self::ConsumerStop,
+            "bridge-caller-start" => Self::BridgeCallerStart,
+            "bridge-caller-stop" => Self::BridgeCallerStop,
+            "bridge-consumer-start" => Self::BridgeConsumerStart,
+            "bridge-consumer-stop" => Self::BridgeConsumerStop,
+            other => Self::Other(other.to_string()),
+        }
+    }
+}
+
+/// a `fifo::info` event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FifoEvent {
+    pub fifo: String,
+    pub action: FifoAction,
+    /// the channel the event is about
+    pub uuid: Option<String>,
+}
+
+impl FifoEvent {
+    /// `None` for events that are not `fifo::info`
+    pub fn from_event(evt: &Event) -> Option<Self> {
+        let Event::Custom(data) = evt else {
+            return None;
+        };
+        if data.get_subclass().as_deref() != Some(SUBCLASS) {
+            return None;
+        }
+        Some(Self {
+            fifo: data.get_body_by_key("FIFO-Name")?,
+            action: FifoAction::parse(&data.get_body_by_key("FIFO-Action")?),
+            uuid: data.get_body_by_key("Unique-ID"),
+        })
+    }
+}
+
+/// callers and consumers of one fifo, by uuid
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FifoState {
+    pub waiting: BTreeSet<String>,
+    pub consumers: BTreeSet<String>,
+}
+
+/// every fifo, kept current from `fifo::info` events
+#[derive(Debug, Default)]
+pub struct FifoTracker {
+    fifos: HashMap<String, FifoState>,
+}
+
+impl FifoTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// seed from [`Conn::fifo_list`]
+    pub fn load(&mut self, fifos: Vec<FifoInfo>) {
+        for fifo in fifos {
+            self.fifos.insert(
+                fifo.name,
+                FifoState {
+                    waiting: fifo.callers.into_iter().map(|caller| caller.uuid).collect(),
+                    consumers: fifo.consumers.into_iter().collect(),
+                },
+            );
+        }
+    }
+
+    pub fn get(&self, fifo: &str) -> Option<&FifoState> {
+        self.fifos.get(fifo)
+    }
+
+    pub fn fifos(&self) -> impl Iterator<Item = (&str, &FifoState)> {
+        self.fifos
+            .iter()
+            .map(|(name, state)| (name.as_str(), state))
+    }
+
+    /// update from one event, other events are ignored
+    pub fn push(&mut self, evt: &Event) -> Option<FifoEvent> {
+        let event = FifoEvent::from_event(evt)?;
+        let state = self.fifos.entry(event.fifo.clone()).or_default();
+        if let Some(uuid) = &event.uuid {
+            match event.action {
+                FifoAction::Push => {
+                    state.waiting.insert(uuid.clone());
+                }
+                FifoAction::Abort | FifoAction::CallerPop | FifoAction::BridgeCallerStart => {
+                    state.waiting.remove(uuid);
+                }
+                FifoAction::ConsumerStart => {
+                    state.consumers.insert(uuid.clone());
+                }
+                FifoAction::ConsumerStop => {
+                    state.consumers.remove(uuid);
+                }
+                _ => {}
+            }
+        }
+        Some(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::json_event;
+
+    #[test]
+    fn parse_outputs() {
+        let count = FifoCount::parse("support@10.0.0.1:1:2:0:0:1").unwrap();
+        assert_eq!(count.name, "support@10.0.0.1");
+        assert_eq!(
+            (count.consumers, count.callers, count.idle_consumers),
+            (1, 2, 1)
+        );
+
+        let fifos = parse_fifo_list(
+            "<fifo_report>\n<fifo name=\"support\" consumer_count=\"1\" caller_count=\"1\" \
+             waiting_count=\"1\" importance=\"0\">\n<callers count=\"1\">\n<caller uuid=\"a\" \
+             status=\"WAITING\" caller_id_name=\"Alice\" caller_id_number=\"1000\"></caller>\n\
+             </callers>\n<consumers count=\"1\">\n<consumer uuid=\"b\"></consumer>\n</consumers>\n\
+             </fifo>\n</fifo_report>",
+        );
+        assert_eq!(fifos.len(), 1);
+        assert_eq!(fifos[0].waiting_count, 1);
+        assert_eq!(fifos[0].callers[0].caller_id_number, "1000");
+        assert_eq!(fifos[0].consumers, ["b"]);
+    }
+
+    #[test]
+    fn parse_bridges() {
+        let fifos = parse_fifo_list(
+            "<fifo_report>\n<fifo name=\"support\" consumer_count=\"1\" caller_count=\"1\" \
+             waiting_count=\"1\" importance=\"0\">\n<outbound></outbound>\n<callers count=\"1\">\n\
+             <caller uuid=\"a\" status=\"WAITING\"></caller>\n</callers>\n<consumers count=\"1\">\n\
+             <consumer uuid=\"b\"></consumer>\n</consumers>\n<bridges count=\"1\">\n<bridge \
+             fifo_name=\"support\" bridge_start=\"2024-05-01 10:00:00\">\n<caller uuid=\"c\" \
+             caller_id_name=\"Carol\" caller_id_number=\"1002\"></caller>\n<consumer uuid=\"d\" \
+             outgoing_uuid=\"e\"></consumer>\n</bridge>\n</bridges>\n</fifo>\n<fifo name=\"sales\" \
+             consumer_count=\"0\" caller_count=\"0\" waiting_count=\"0\" importance=\"0\">\n\
+             <outbound></outbound>\n<callers count=\"0\"></callers>\n<consumers count=\"0\">\
+             </consumers>\n<bridges count=\"0\"></bridges>\n</fifo>\n</fifo_report>",
+        );
+        assert_eq!(fifos.len(), 2);
+        let callers: Vec<_> = fifos[0].callers.iter().map(|c| c.uuid.as_str()).collect();
+        assert_eq!(callers, ["a"]);
+        assert_eq!(fifos[0].consumers, ["b"]);
+        assert!(fifos[1].callers.is_empty() && fifos[1].consumers.is_empty());
+    }
+
+    #[test]
+    fn track_fifos() {
+        let event = |action: &str, uuid: &str| -> Event {
+            let body = serde_json::json!({
+                "Event-Name": "CUSTOM",
+                "Event-Subclass": "fifo::info",
+                "FIFO-Name": "support",
+                "FIFO-Action": action,
+                "Unique-ID": uuid,
+            });
+            json_event(body)
+        };
+        let mut tracker = FifoTracker::new();
+        tracker.push(&event("push", "a"));
+        tracker.push(&event("push", "b"));
+        tracker.push(&event("consumer_start", "c"));
+        tracker.push(&event("abort", "a"));
+        let state = tracker.get("support").unwrap();
+        assert_eq!(state.waiting.iter().collect::<Vec<_>>(), ["b"]);
+        assert!(state.consumers.contains("c"));
+        assert_eq!(
+            tracker
+                .push(&event("bridge-caller-start", "b"))
+                .unwrap()
+                .action,
+            FifoAction::BridgeCallerStart
+        );
+        assert!(tracker.get("support").unwrap().waiting.is_empty());
+    }
+}
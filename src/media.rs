@@ -0,0 +1,597 @@
+//! playback and recording that can be awaited
+//!
+//! [`Channel::play`] and [`Channel::record`] queue the `playback` and `record`
+//! applications and follow them to their CHANNEL_EXECUTE_COMPLETE, the
+//! PLAYBACK_STOP / RECORD_STOP before it tells why they stopped. awaiting the
+//! returned [`Media`] gives that reason.
+//! [`Channel::play_and_get_digits`] runs an IVR prompt and returns the digits.
+//!
+//! ```no_run
+//! # async fn demo(conn: esl_rs::conn::Conn) -> esl_rs::error::Result<()> {
+//! use esl_rs::media::{MediaStop, RecordOptions};
+//! use std::time::Duration;
+//!
+//! let channel = conn.channel("7f4d")?;
+//! if let MediaStop::Dtmf(digit) = channel.play("ivr/ivr-welcome.wav").await?.await? {
+//!     println!("caller pressed {}", digit);
+//! }
+//! let options = RecordOptions {
+//!     limit: Some(Duration::from_secs(60)),
+//!     ..Default::default()
+//! };
+//! channel.record("/tmp/message.wav", options).await?.await?;
+//! # Ok(())
+//! # }
+//! ```
+
+use std::{
+    future::{Future, IntoFuture},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use tokio::sync::{broadcast, oneshot};
+use tracing::warn;
+
+use crate::{
+    channel::Channel,
+    command::{Command, EventFormat},
+    error::{EslError, Result},
+    event::{Event, EventData},
+    hangup::HangupCause,
+    reply::reply_result,
+};
+
+/// events a [`Media`] follows
+const MEDIA_EVENTS: [&str; 4] = [
+    "PLAYBACK_STOP",
+    "RECORD_STOP",
+    "CHANNEL_EXECUTE_COMPLETE",
+    "CHANNEL_HANGUP",
+];
+
+/// the variable the `play_and_get_digits` tagged `event_uuid` stores the
+/// digits in, one per call so what an earlier call left is never read back.
+/// it is unset once the call is over
+fn digits_var(event_uuid: &str) -> String {
+    format!("esl_rs_digits_{}", event_uuid.replace('-', ""))
+}
+
+/// why a playback or recording ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MediaStop {
+    /// played to the end, or the recording hit its limit or silence
+    Finished,
+    /// a terminator digit was pressed
+    Dtmf(String),
+    /// stopped with `uuid_break`, e.g. by [`Media::stop`]
+    Broken,
+    Hangup(Option<HangupCause>),
+}
+
+/// arguments of the `record` application
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecordOptions {
+    /// longest recording, no limit when `None`
+    pub limit: Option<Duration>,
+    /// energy level below which audio counts as silence
+    pub silence_threshold: Option<u32>,
+    /// how long the silence lasts before the recording stops
+    pub silence_hits: Option<Duration>,
+    /// digits that end the recording, `playback_terminators` is set by an app
+    /// queued just before `record` and put back by one queued after it
+    pub terminators: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Playback,
+    Record,
+}
+
+/// a running playback or recording
+///
+/// awaiting it gives the [`MediaStop`] reason. dropping it does not stop the
+/// media, use [`Media::stop`]
+#[derive(Debug)]
+pub struct Media {
+    channel: Channel,
+    path: String,
+    stopped: Arc<AtomicBool>,
+    outcome: oneshot::Receiver<Result<MediaStop>>,
+}
+
+impl Media {
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// `uuid_break`, the media then resolves as [`MediaStop::Broken`]
+    pub async fn stop(&self) -> Result<()> {
+        self.stopped.store(true, Ordering::SeqCst);
+        self.channel.break_media(false).await
+    }
+
+    /// follow `events` until the application tagged `event_uuid` completes on
+    /// `channel`
+    fn follow(
+        mut events: broadcast::Receiver<Event>,
+        channel: Channel,
+        kind: Kind,
+        path: String,
+        event_uuid: String,
+    ) -> Self {
+        let stopped = Arc::new(AtomicBool::new(false));
+        let (mut outcome_tx, outcome) = oneshot::channel();
+        let (uuid, file, broken) = (channel.uuid().to_string(), path.clone(), stopped.clone());
+        tokio::spawn(async move {
+            // the stop event comes first, its path may have the sound prefix added
+            let mut stop = None;
+            let outcome = loop {
+                let evt = tokio::select! {
+                    evt = events.recv() => evt,
+                    _ = outcome_tx.closed() => return,
+                };
+                let evt = match evt {
+                    Ok(evt) => evt,
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        warn!("{:?} of {} missed {} events", kind, file, n);
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => {
+                        break Err(EslError::ConnectionError("connection closed".to_string()));
+                    }
+                };
+                if evt.get_body_by_key("Unique-ID").as_deref() != Some(uuid.as_str()) {
+                    continue;
+                }
+                match (evt, kind) {
+                    (Event::ChannelHangup(data), _) => {
+                        break Ok(MediaStop::Hangup(data.hangup_cause()))
+                    }
+                    (Event::PlaybackStop(data), Kind::Playback)
+                    | (Event::RecordStop(data), Kind::Record) => stop = Some(data),
+                    (Event::ChannelExecuteComplete(data), _)
+                        if data.get_body_by_key("Application-UUID").as_deref()
+                            == Some(event_uuid.as_str()) =>
+                    {
+                        let data = stop.as_ref().unwrap_or(&data);
+                        break Ok(stop_reason(data, broken.load(Ordering::SeqCst)));
+                    }
+                    // that stop belonged to an application queued before
+                    (Event::ChannelExecuteComplete(_), _) => stop = None,
+                    _ => {}
+                }
+            };
+            let _ = outcome_tx.send(outcome);
+        });
+        Self {
+            channel,
+            path,
+            stopped,
+            outcome,
+        }
+    }
+}
+
+/// the reason carried by PLAYBACK_STOP / RECORD_STOP
+fn stop_reason(data: &EventData, stopped: bool) -> MediaStop {
+    if let Some(digit) = data
+        .get_var("playback_terminator_used")
+        .filter(|digit| !digit.is_empty())
+    {
+        return MediaStop::Dtmf(digit);
+    }
+    if data.get_body_by_key("Channel-Call-State").as_deref() == Some("HANGUP") {
+        return MediaStop::Hangup(data.hangup_cause());
+    }
+    if stopped || data.get_body_by_key("Playback-Status").as_deref() == Some("break") {
+        return MediaStop::Broken;
+    }
+    MediaStop::Finished
+}
+
+/// wait for CHANNEL_EXECUTE_COMPLETE of the application tagged `event_uuid`
+async fn wait_complete(
+    mut events: broadcast::Receiver<Event>,
+    uuid: &str,
+    event_uuid: &str,
+) -> Result<EventData> {
+    loop {
+        let evt = match events.recv().await {
+            Ok(evt) => evt,
+            Err(broadcast::error::RecvError::Lagged(n)) => {
+                warn!("execute {} missed {} events", event_uuid, n);
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => {
+                return Err(EslError::ConnectionError("connection closed".to_string()));
+            }
+        };
+        if evt.get_body_by_key("Unique-ID").as_deref() != Some(uuid) {
+            continue;
+        }
+        match evt {
+            Event::ChannelExecuteComplete(data)
+                if data.get_body_by_key("Application-UUID").as_deref() == Some(event_uuid) =>
+            {
+                return Ok(data)
+            }
+            Event::ChannelHangup(data) => {
+                return Err(EslError::Hangup(
+                    data.hangup_cause().unwrap_or(HangupCause::NormalClearing),
+                ))
+            }
+            _ => {}
+        }
+    }
+}
+
+/// the digits `play_and_get_digits` stored in `var`, or why there are none
+fn digits_result(data: &EventData, var: &str) -> Result<String> {
+    let non_empty = |name: &str| data.get_var(name).filter(|value| !value.is_empty());
+    if let Some(digits) = non_empty(var) {
+        return Ok(digits);
+    }
+    match non_empty(&format!("{}_invalid", var)) {
+        Some(input) => Err(EslError::InvalidInput(input)),
+        None => Err(EslError::NoInput),
+    }
+}
+
+impl IntoFuture for Media {
+    type Output = Result<MediaStop>;
+    type IntoFuture = Pin<Box<dyn Future<Output = Result<MediaStop>> + Send>>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(async move {
+            self.outcome
+                .await
+                .map_err(|_| EslError::ConnectionError("media watcher gone".to_string()))?
+        })
+    }
+}
+
+impl Channel {
+    /// queue `playback` of `file`, see [`Media`]
+    pub async fn play(&self, file: &str) -> Result<Media> {
+        let event_uuid = uuid::Uuid::new_v4().to_string();
+        let events = self.listen().await?;
+        self.execute_tagged("playback", file, Some(&event_uuid))
+            .await?;
+        Ok(Media::follow(
+            events,
+            self.clone(),
+            Kind::Playback,
+            file.to_string(),
+            event_uuid,
+        ))
+    }
+
+    /// queue the `record` application, writing to `path`, see [`Media`]
+    pub async fn record(&self, path: &str, options: RecordOptions) -> Result<Media> {
+        // the arguments are split on spaces
+        if path.is_empty() || path.contains(char::is_whitespace) {
+            return Err(EslError::InvalidArgument(format!("record path {:?}", path)));
+        }
+        let mut args = vec![path.to_string()];
+        let limit = options.limit.map_or(0, |limit| limit.as_secs());
+        if let Some(threshold) = options.silence_threshold {
+            let hits = options.silence_hits.map_or(3, |hits| hits.as_secs());
+            args.extend([limit.to_string(), threshold.to_string(), hits.to_string()]);
+        } else if limit > 0 {
+            args.push(limit.to_string());
+        }
+        let previous = match &options.terminators {
+            Some(terminators) => {
+                let previous = self.get_var("playback_terminators").await?;
+                // queued too, apps ahead of the recording keep their terminators
+                self.execute("set", &format!("playback_terminators={}", terminators))
+                    .await?;
+                Some(previous)
+            }
+            None => None,
+        };
+        let event_uuid = uuid::Uuid::new_v4().to_string();
+        let events = self.listen().await?;
+        self.execute_tagged("record", &args.join(" "), Some(&event_uuid))
+            .await?;
+        // queued behind the recording, so it runs once that is over
+        match previous {
+            Some(Some(previous)) => {
+                self.execute("set", &format!("playback_terminators={}", previous))
+                    .await?
+            }
+            Some(None) => self.execute("unset", "playback_terminators").await?,
+            None => {}
+        }
+        Ok(Media::follow(
+            events,
+            self.clone(),
+            Kind::Record,
+            path.to_string(),
+            event_uuid,
+        ))
+    }
+
+    /// run `play_and_get_digits` and wait for it to finish
+    ///
+    /// plays `prompt` up to `tries` times, waiting `timeout` for `min` to `max`
+    /// digits matching `regex` (any digits when `None`), playing
+    /// `invalid_prompt` after a bad entry. fails with [`EslError::NoInput`]
+    /// when nothing was entered, [`EslError::InvalidInput`] with the last
+    /// entry when none matched, and [`EslError::Hangup`] when the caller hung up
+    #[allow(clippy::too_many_arguments)]
+    pub async fn play_and_get_digits(
+        &self,
+        min: u32,
+        max: u32,
+        tries: u32,
+        timeout: Duration,
+        terminators: &str,
+        prompt: &str,
+        invalid_prompt: Option<&str>,
+        regex: Option<&str>,
+    ) -> Result<String> {
+        if min > max || max == 0 || tries == 0 {
+            return Err(EslError::InvalidArgument(format!(
+                "play_and_get_digits min {} max {} tries {}",
+                min, max, tries
+            )));
+        }
+        let terminators = match terminators {
+            "" => "none",
+            terminators => terminators,
+        };
+        let event_uuid = uuid::Uuid::new_v4().to_string();
+        let var = digits_var(&event_uuid);
+        let args = [
+            min.to_string(),
+            max.to_string(),
+            tries.to_string(),
+            timeout.as_millis().to_string(),
+            terminators.to_string(),
+            prompt.to_string(),
+            invalid_prompt.unwrap_or("silence_stream://250").to_string(),
+            var.clone(),
+            regex.unwrap_or("\\d+").to_string(),
+        ];
+        // the arguments are split on spaces
+        if let Some(arg) = args
+            .iter()
+            .find(|arg| arg.is_empty() || arg.contains(char::is_whitespace))
+        {
+            return Err(EslError::InvalidArgument(format!(
+                "play_and_get_digits argument {:?}",
+                arg
+            )));
+        }
+        let events = self.listen().await?;
+        self.execute_tagged("play_and_get_digits", &args.join(" "), Some(&event_uuid))
+            .await?;
+        // queued behind it: CHANNEL_EXECUTE_COMPLETE still carries the input,
+        // the channel keeps nothing
+        for name in [var.clone(), format!("{}_invalid", var)] {
+            self.execute("unset", &name).await?;
+        }
+        let complete = wait_complete(events, self.uuid(), &event_uuid).await?;
+        digits_result(&complete, &var)
+    }
+
+    /// listen before the media can stop
+    async fn listen(&self) -> Result<broadcast::Receiver<Event>> {
+        let conn = self.conn();
+        let events = conn.events();
+        reply_result(
+            conn.execute(&Command::event(EventFormat::Json, MEDIA_EVENTS))
+                .await?,
+        )?;
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tests::{json_event, mock_freeswitch, mock_freeswitch_steps},
+        Esl,
+    };
+
+    #[tokio::test]
+    async fn play_until_dtmf() {
+        let addr = mock_freeswitch("", 0).await;
+        let conn = Esl::inbound(addr, "ClueCon").await.unwrap();
+        let (tx, _) = broadcast::channel(16);
+        let media = Media::follow(
+            tx.subscribe(),
+            conn.channel("7f4d").unwrap(),
+            Kind::Playback,
+            "ivr/welcome.wav".to_string(),
+            "tag".to_string(),
+        );
+        tx.send(json_event(serde_json::json!({"Event-Name":"PLAYBACK_STOP","Unique-ID":"7f4d","Playback-File-Path":"other.wav"})))
+        .unwrap();
+        tx.send(json_event(serde_json::json!({"Event-Name":"CHANNEL_EXECUTE_COMPLETE","Unique-ID":"7f4d","Application-UUID":"other"})))
+        .unwrap();
+        tx.send(json_event(serde_json::json!({"Event-Name":"RECORD_STOP","Unique-ID":"7f4d","Record-File-Path":"ivr/welcome.wav"})))
+        .unwrap();
+        // the sound prefix makes the path absolute
+        tx.send(json_event(
+            serde_json::json!({"Event-Name":"PLAYBACK_STOP","Unique-ID":"7f4d",
+                "Playback-File-Path":"/usr/share/freeswitch/sounds/en/us/callie/ivr/welcome.wav",
+                "Playback-Status":"break","variable_playback_terminator_used":"#"}),
+        ))
+        .unwrap();
+        tx.send(json_event(serde_json::json!({"Event-Name":"CHANNEL_EXECUTE_COMPLETE","Unique-ID":"7f4d","Application-UUID":"tag"})))
+        .unwrap();
+        assert_eq!(media.await.unwrap(), MediaStop::Dtmf("#".to_string()));
+    }
+
+    #[tokio::test]
+    async fn collect_digits() {
+        let (first, second) = (digits_var("menu-1"), digits_var("menu-2"));
+        let (tx, _) = broadcast::channel(16);
+        let events = tx.subscribe();
+        tx.send(json_event(
+            serde_json::json!({"Event-Name":"CHANNEL_EXECUTE_COMPLETE","Unique-ID":"7f4d",
+                "Application-UUID":"other", format!("variable_{}", first):"9"}),
+        ))
+        .unwrap();
+        tx.send(json_event(
+            serde_json::json!({"Event-Name":"CHANNEL_EXECUTE_COMPLETE","Unique-ID":"7f4d",
+                "Application":"play_and_get_digits","Application-UUID":"menu-1",
+                format!("variable_{}", first):"42"}),
+        ))
+        .unwrap();
+        let complete = wait_complete(events, "7f4d", "menu-1").await.unwrap();
+        assert_eq!(digits_result(&complete, &first).unwrap(), "42");
+
+        // a second call on the same channel still sees the digits of the first
+        let events = tx.subscribe();
+        tx.send(json_event(
+            serde_json::json!({"Event-Name":"CHANNEL_EXECUTE_COMPLETE","Unique-ID":"7f4d",
+                "Application":"play_and_get_digits","Application-UUID":"menu-2",
+                format!("variable_{}", first):"42", format!("variable_{}_invalid", second):"7"}),
+        ))
+        .unwrap();
+        let complete = wait_complete(events, "7f4d", "menu-2").await.unwrap();
+        assert_eq!(
+            digits_result(&complete, &second),
+            Err(EslError::InvalidInput("7".to_string()))
+        );
+
+        let events = tx.subscribe();
+        tx.send(json_event(serde_json::json!({"Event-Name":"CHANNEL_HANGUP","Unique-ID":"7f4d","Hangup-Cause":"NORMAL_CLEARING"})))
+        .unwrap();
+        assert_eq!(
+            wait_complete(events, "7f4d", "menu-1").await.unwrap_err(),
+            EslError::Hangup(HangupCause::NormalClearing)
+        );
+
+        assert_eq!(
+            digits_result(&json_event(serde_json::json!({})), &first),
+            Err(EslError::NoInput)
+        );
+    }
+
+    #[tokio::test]
+    async fn digits_variables_unset() {
+        let ok = "Content-Type: command/reply\nReply-Text: +OK\n\n";
+        let hangup = r#"{"Event-Name":"CHANNEL_HANGUP","Unique-ID":"7f4d","Hangup-Cause":"NORMAL_CLEARING"}"#;
+        let last = format!(
+            "{}Content-Length: {}\nContent-Type: text/event-json\n\n{}",
+            ok,
+            hangup.len(),
+            hangup
+        );
+        let (addr, mut commands) = mock_freeswitch_steps(vec![
+            (1, ok),
+            (1, ok),
+            (1, ok),
+            (1, Box::leak(last.into_boxed_str())),
+        ])
+        .await;
+        let conn = Esl::inbound(addr, "ClueCon").await.unwrap();
+        let channel = conn.channel("7f4d").unwrap();
+        let err = channel
+            .play_and_get_digits(
+                1,
+                4,
+                3,
+                Duration::from_secs(5),
+                "#",
+                "ivr/menu.wav",
+                None,
+                None,
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(err, EslError::Hangup(HangupCause::NormalClearing));
+
+        let mut sent = Vec::new();
+        while let Ok(command) = commands.try_recv() {
+            sent.push(command);
+        }
+        let event_uuid = sent[1].rsplit("Event-UUID: ").next().unwrap();
+        let var = digits_var(event_uuid);
+        assert!(sent[1].contains(&format!(" {} ", var)));
+        let unset = |name: &str| {
+            format!(
+                "sendmsg 7f4d\ncall-command: execute\nexecute-app-name: unset\nexecute-app-arg: {}",
+                name
+            )
+        };
+        assert_eq!(sent[2..], [unset(&var), unset(&format!("{}_invalid", var))]);
+    }
+
+    #[tokio::test]
+    async fn record_terminators_queued() {
+        let ok = "Content-Type: command/reply\nReply-Text: +OK\n\n";
+        let (addr, mut commands) = mock_freeswitch_steps(vec![
+            (
+                1,
+                "Content-Type: api/response\nContent-Length: 7\n\n_undef_",
+            ),
+            (1, ok),
+            (1, ok),
+            (1, ok),
+            (1, ok),
+        ])
+        .await;
+        let conn = Esl::inbound(addr, "ClueCon").await.unwrap();
+        let channel = conn.channel("7f4d").unwrap();
+        let options = RecordOptions {
+            terminators: Some("#".to_string()),
+            ..Default::default()
+        };
+        let _media = channel.record("/tmp/a.wav", options).await.unwrap();
+
+        let mut sent = Vec::new();
+        while let Ok(command) = commands.try_recv() {
+            sent.push(command);
+        }
+        let app = |name: &str, arg: &str| {
+            format!(
+                "sendmsg 7f4d\ncall-command: execute\nexecute-app-name: {}\nexecute-app-arg: {}",
+                name, arg
+            )
+        };
+        assert_eq!(sent[0], "api uuid_getvar 7f4d playback_terminators");
+        // nothing is set on the channel until the apps queued ahead are done
+        assert_eq!(sent[1], app("set", "playback_terminators=#"));
+        assert!(sent[3].starts_with(&app("record", "/tmp/a.wav")));
+        assert_eq!(sent[4], app("unset", "playback_terminators"));
+    }
+
+    #[test]
+    fn stop_reasons() {
+        assert_eq!(
+            stop_reason(
+                &json_event(serde_json::json!({"Playback-Status":"done"})),
+                false
+            ),
+            MediaStop::Finished
+        );
+        assert_eq!(
+            stop_reason(
+                &json_event(serde_json::json!({"Record-File-Path":"/tmp/a.wav"})),
+                true
+            ),
+            MediaStop::Broken
+        );
+        assert_eq!(
+            stop_reason(
+                &json_event(
+                    serde_json::json!({"Channel-Call-State":"HANGUP","Hangup-Cause":"NORMAL_CLEARING"})
+                ),
+                false
+            ),
+            MediaStop::Hangup(Some(HangupCause::NormalClearing))
+        );
+    }
+}
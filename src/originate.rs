@@ -0,0 +1,784 @@
+//! `originate` command builder and parser
+//!
+//! a dial string is `<enterprise vars>{global vars}[leg vars]endpoint`, legs
+//! joined by `,` ring at the same time, `|` tries the next group when the
+//! previous one failed and `:_:` runs independent groups in parallel.
+//!
+//! ```
+//! use esl_rs::originate::Originate;
+//!
+//! let originate = Originate::new("user/1000")
+//!     .var("ignore_early_media", "true")
+//!     .leg_var("origination_caller_id_name", "Front Desk")
+//!     .also("user/1001")
+//!     .failover("sofia/gateway/pstn/5551234")
+//!     .app("park", None);
+//! assert_eq!(
+//!     originate.to_string(),
+//!     "originate {ignore_early_media=true}[origination_caller_id_name='Front Desk']user/1000,user/1001|sofia/gateway/pstn/5551234 &park"
+//! );
+//! assert_eq!(originate.to_string().parse::<Originate>().unwrap(), originate);
+//! ```
+
+use std::{fmt, future::Future, future::IntoFuture, pin::Pin, str::FromStr};
+
+use tokio::sync::{broadcast, oneshot, watch};
+use tracing::warn;
+
+use crate::{
+    command::Command,
+    conn::Conn,
+    error::{EslError, Result},
+    event::Event,
+    hangup::HangupCause,
+    reply::{reply_result, CommandReply},
+};
+
+pub type Vars = Vec<(String, String)>;
+
+/// one endpoint with its `[]` variables
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Leg {
+    pub vars: Vars,
+    pub endpoint: String,
+}
+
+/// legs sharing one set of `{}` variables
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Group {
+    pub vars: Vars,
+    /// tried in order (`|`), the legs of each entry ring together (`,`)
+    pub legs: Vec<Vec<Leg>>,
+}
+
+/// where the answered call goes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// `&app(args)`
+    App { name: String, args: Option<String> },
+    /// an extension run through `dialplan` and `context`
+    Extension(String),
+}
+
+impl Default for Target {
+    fn default() -> Self {
+        Self::App {
+            name: "park".to_string(),
+            args: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Originate {
+    /// `<>` variables, shared by all `:_:` groups
+    pub enterprise_vars: Vars,
+    /// `:_:` separated groups
+    pub groups: Vec<Group>,
+    pub target: Target,
+    pub dialplan: Option<String>,
+    pub context: Option<String>,
+    pub caller_id_name: Option<String>,
+    pub caller_id_number: Option<String>,
+    /// seconds
+    pub timeout: Option<u32>,
+}
+
+impl Originate {
+    /// dial `endpoint` and park the call
+    pub fn new(endpoint: impl Into<String>) -> Self {
+        Self {
+            groups: vec![Group {
+                vars: Vars::new(),
+                legs: vec![vec![leg(endpoint)]],
+            }],
+            ..Default::default()
+        }
+    }
+
+    /// `{}` variable of the current group
+    pub fn var(mut self, key: impl Into<String>, value: impl ToString) -> Self {
+        if let Some(group) = self.groups.last_mut() {
+            group.vars.push((key.into(), value.to_string()));
+        }
+        self
+    }
+
+    /// `[]` variable of the last added endpoint
+    pub fn leg_var(mut self, key: impl Into<String>, value: impl ToString) -> Self {
+        if let Some(leg) = self
+            .groups
+            .last_mut()
+            .and_then(|group| group.legs.last_mut())
+            .and_then(|legs| legs.last_mut())
+        {
+            leg.vars.push((key.into(), value.to_string()));
+        }
+        self
+    }
+
+    /// `<>` variable, applies to every enterprise group
+    pub fn enterprise_var(mut self, key: impl Into<String>, value: impl ToString) -> Self {
+        self.enterprise_vars.push((key.into(), value.to_string()));
+        self
+    }
+
+    /// ring `endpoint` together with the previous one (`,`)
+    pub fn also(mut self, endpoint: impl Into<String>) -> Self {
+        match self
+            .groups
+            .last_mut()
+            .and_then(|group| group.legs.last_mut())
+        {
+            Some(legs) => legs.push(leg(endpoint)),
+            None => return self.enterprise(endpoint),
+        }
+        self
+    }
+
+    /// try `endpoint` when the previous ones failed (`|`)
+    pub fn failover(mut self, endpoint: impl Into<String>) -> Self {
+        match self.groups.last_mut() {
+            Some(group) => group.legs.push(vec![leg(endpoint)]),
+            None => return self.enterprise(endpoint),
+        }
+        self
+    }
+
+    /// start an independent group (`:_:`), it gets its own `{}` variables
+    pub fn enterprise(mut self, endpoint: impl Into<String>) -> Self {
+        self.groups.push(Group {
+            vars: Vars::new(),
+            legs: vec![vec![leg(endpoint)]],
+        });
+        self
+    }
+
+    /// run `&name(args)` once answered
+    pub fn app(mut self, name: impl Into<String>, args: Option<&str>) -> Self {
+        self.target = Target::App {
+            name: name.into(),
+            args: args.map(str::to_string),
+        };
+        self
+    }
+
+    /// send the answered call to `extension` in the dialplan
+    pub fn extension(mut self, extension: impl Into<String>) -> Self {
+        self.target = Target::Extension(extension.into());
+        self
+    }
+
+    pub fn dialplan(mut self, dialplan: impl Into<String>) -> Self {
+        self.dialplan = Some(dialplan.into());
+        self
+    }
+
+    pub fn context(mut self, context: impl Into<String>) -> Self {
+        self.context = Some(context.into());
+        self
+    }
+
+    pub fn caller_id(mut self, name: impl Into<String>, number: impl Into<String>) -> Self {
+        self.caller_id_name = Some(name.into());
+        self.caller_id_number = Some(number.into());
+        self
+    }
+
+    /// originate timeout in seconds
+    pub fn timeout(mut self, seconds: u32) -> Self {
+        self.timeout = Some(seconds);
+        self
+    }
+
+    /// `origination_uuid` of the first group or its first endpoint
+    pub fn uuid(&self) -> Option<&str> {
+        let group = self.groups.first()?;
+        let leg = group.legs.first().and_then(|legs| legs.first());
+        group
+            .vars
+            .iter()
+            .chain(leg.into_iter().flat_map(|leg| &leg.vars))
+            .find(|(key, _)| key == "origination_uuid")
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// the call url, without target and options
+    pub fn dial_string(&self) -> String {
+        let mut out = String::new();
+        if !self.enterprise_vars.is_empty() {
+            out.push_str(&format!("<{}>", vars_to_string(&self.enterprise_vars)));
+        }
+        let groups: Vec<_> = self
+            .groups
+            .iter()
+            .map(|group| {
+                let mut out = String::new();
+                if !group.vars.is_empty() {
+                    out.push_str(&format!("{{{}}}", vars_to_string(&group.vars)));
+                }
+                let legs: Vec<_> = group
+                    .legs
+                    .iter()
+                    .map(|legs| {
+                        let legs: Vec<_> = legs
+                            .iter()
+                            .map(|leg| match leg.vars.is_empty() {
+                                true => leg.endpoint.clone(),
+                                false => format!("[{}]{}", vars_to_string(&leg.vars), leg.endpoint),
+                            })
+                            .collect();
+                        legs.join(",")
+                    })
+                    .collect();
+                out.push_str(&legs.join("|"));
+                out
+            })
+            .collect();
+        out.push_str(&groups.join(":_:"));
+        out
+    }
+
+    /// parse a dial string, see [`Self::dial_string`]. the target is `&park`
+    pub fn from_dial_string(dial: &str) -> Result<Self> {
+        let invalid = |reason: &str| EslError::InvalidArgument(format!("{}: {:?}", reason, dial));
+        let mut rest = dial.trim();
+        let mut originate = Self::default();
+        while let Some(block) = rest.strip_prefix('<') {
+            let end = block_end(block, '>').ok_or_else(|| invalid("unclosed <"))?;
+            originate.enterprise_vars.extend(parse_vars(&block[..end]));
+            rest = &block[end + 1..];
+        }
+        for group in split_top(rest, ":_:") {
+            let mut group_rest = group;
+            let mut vars = Vars::new();
+            while let Some(block) = group_rest.strip_prefix('{') {
+                let end = block_end(block, '}').ok_or_else(|| invalid("unclosed {"))?;
+                vars.extend(parse_vars(&block[..end]));
+                group_rest = &block[end + 1..];
+            }
+            let mut legs = Vec::new();
+            for failover in split_top(group_rest, "|") {
+                let mut together = Vec::new();
+                for endpoint in split_top(failover, ",") {
+                    let mut endpoint = endpoint.trim();
+                    let mut vars = Vars::new();
+                    while let Some(block) = endpoint.strip_prefix('[') {
+                        let end = block_end(block, ']').ok_or_else(|| invalid("unclosed ["))?;
+                        vars.extend(parse_vars(&block[..end]));
+                        endpoint = &block[end + 1..];
+                    }
+                    if endpoint.is_empty() {
+                        return Err(invalid("empty endpoint"));
+                    }
+                    together.push(Leg {
+                        vars,
+                        endpoint: endpoint.to_string(),
+                    });
+                }
+                legs.push(together);
+            }
+            originate.groups.push(Group { vars, legs });
+        }
+        Ok(originate)
+    }
+}
+
+impl fmt::Display for Originate {
+    /// the full `originate` command, for [`crate::conn::Conn::api`] or `bgapi`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "originate {}", self.dial_string())?;
+        let target = match &self.target {
+            Target::App {
+                name,
+                args: Some(args),
+            } => format!("&{}({})", name, args),
+            Target::App { name, args: None } => format!("&{}", name),
+            Target::Extension(extension) => extension.clone(),
+        };
+        write!(f, " {}", quote(&target))?;
+        // positional, so earlier ones are filled in once a later one is set
+        let timeout = self.timeout.map(|t| t.to_string());
+        let options = [
+            self.dialplan.as_deref(),
+            self.context.as_deref(),
+            self.caller_id_name.as_deref(),
+            self.caller_id_number.as_deref(),
+            timeout.as_deref(),
+        ];
+        let defaults = ["XML", "default", "undef", "undef", "60"];
+        if let Some(last) = options.iter().rposition(Option::is_some) {
+            for (option, default) in options.iter().zip(defaults).take(last + 1) {
+                write!(f, " {}", quote(option.unwrap_or(default)))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Originate {
+    type Err = EslError;
+
+    /// parse an `originate` command, the `originate` itself is optional
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let s = s.strip_prefix("originate ").unwrap_or(s).trim_start();
+        let mut args = split_args(s).into_iter();
+        let dial = args
+            .next()
+            .ok_or_else(|| EslError::InvalidArgument("empty originate".to_string()))?;
+        let mut originate = Self::from_dial_string(&dial)?;
+        if let Some(target) = args.next() {
+            originate.target = match target.strip_prefix('&') {
+                Some(app) => match app.split_once('(') {
+                    Some((name, args)) => {
+                        let args = args.strip_suffix(')').unwrap_or(args);
+                        Target::App {
+                            name: name.to_string(),
+                            args: (!args.is_empty()).then(|| args.to_string()),
+                        }
+                    }
+                    None => Target::App {
+                        name: app.to_string(),
+                        args: None,
+                    },
+                },
+                None => Target::Extension(target),
+            };
+        }
+        let mut option = || args.next().filter(|arg| arg != "undef");
+        originate.dialplan = option();
+        originate.context = option();
+        originate.caller_id_name = option();
+        originate.caller_id_number = option();
+        originate.timeout = option().and_then(|t| t.parse().ok());
+        Ok(originate)
+    }
+}
+
+/// how far an originate got, see [`OriginateHandle`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Progress {
+    /// the job was accepted, nothing heard from the far end yet
+    Trying,
+    /// CHANNEL_PROGRESS
+    Ringing,
+    /// CHANNEL_PROGRESS_MEDIA
+    EarlyMedia,
+    /// CHANNEL_ANSWER
+    Answered,
+}
+
+/// what to subscribe for [`Conn::originate`]
+pub const ORIGINATE_EVENTS: [&str; 4] = [
+    "BACKGROUND_JOB",
+    "CHANNEL_PROGRESS",
+    "CHANNEL_PROGRESS_MEDIA",
+    "CHANNEL_ANSWER",
+];
+
+/// a running `bgapi originate`
+///
+/// awaiting it gives the uuid of the answered channel, or
+/// [`EslError::Hangup`] with the cause the call failed with
+#[derive(Debug)]
+pub struct OriginateHandle {
+    uuid: String,
+    job_uuid: String,
+    progress: watch::Receiver<Progress>,
+    outcome: oneshot::Receiver<Result<String>>,
+}
+
+impl OriginateHandle {
+    /// `origination_uuid` of the new channel
+    pub fn uuid(&self) -> &str {
+        &self.uuid
+    }
+
+    pub fn job_uuid(&self) -> &str {
+        &self.job_uuid
+    }
+
+    pub fn progress(&self) -> Progress {
+        *self.progress.borrow()
+    }
+
+    /// wait for the next progress, `None` once the originate finished
+    pub async fn next_progress(&mut self) -> Option<Progress> {
+        self.progress.changed().await.ok()?;
+        Some(*self.progress.borrow_and_update())
+    }
+
+    /// follow `uuid` and `job_uuid` in `events` until the job finishes
+    fn follow(mut events: broadcast::Receiver<Event>, uuid: String, job_uuid: String) -> Self {
+        let (progress_tx, progress) = watch::channel(Progress::Trying);
+        let (mut outcome_tx, outcome) = oneshot::channel();
+        let (channel, job) = (uuid.clone(), job_uuid.clone());
+        tokio::spawn(async move {
+            let advance = |next: Progress| {
+                progress_tx.send_if_modified(|progress| {
+                    let modified = next > *progress;
+                    *progress = (*progress).max(next);
+                    modified
+                });
+            };
+            let outcome = loop {
+                let evt = tokio::select! {
+                    evt = events.recv() => evt,
+                    // nobody is waiting any more
+                    _ = outcome_tx.closed() => return,
+                };
+                let evt = match evt {
+                    Ok(evt) => evt,
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        warn!("originate {} missed {} events", channel, n);
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => {
+                        break Err(EslError::ConnectionError("connection closed".to_string()));
+                    }
+                };
+                let is_channel =
+                    evt.get_body_by_key("Unique-ID").as_deref() == Some(channel.as_str());
+                match evt {
+                    Event::BackgroundJob(data)
+                        if data.get_body_by_key("Job-UUID").as_deref() == Some(job.as_str()) =>
+                    {
+                        break match CommandReply::parse(data.content().unwrap_or_default()) {
+                            CommandReply::Ok(uuid) => {
+                                advance(Progress::Answered);
+                                Ok(if uuid.is_empty() { channel } else { uuid })
+                            }
+                            CommandReply::Err(cause) => Err(EslError::Hangup(
+                                cause.parse().unwrap_or(HangupCause::Unspecified),
+                            )),
+                            CommandReply::Usage(usage) => Err(EslError::Usage(usage)),
+                        };
+                    }
+                    Event::ChannelProgress(_) if is_channel => advance(Progress::Ringing),
+                    Event::ChannelProgressMedia(_) if is_channel => advance(Progress::EarlyMedia),
+                    Event::ChannelAnswer(_) if is_channel => advance(Progress::Answered),
+                    _ => {}
+                }
+            };
+            let _ = outcome_tx.send(outcome);
+        });
+        Self {
+            uuid,
+            job_uuid,
+            progress,
+            outcome,
+        }
+    }
+}
+
+impl IntoFuture for OriginateHandle {
+    type Output = Result<String>;
+    type IntoFuture = Pin<Box<dyn Future<Output = Result<String>> + Send>>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(async move {
+            self.outcome
+                .await
+                .map_err(|_| EslError::ConnectionError("originate watcher gone".to_string()))?
+        })
+    }
+}
+
+impl Conn {
+    /// run `originate` in the background and follow it, see [`OriginateHandle`]
+    ///
+    /// `origination_uuid` is assigned when missing. the subscription is left
+    /// as it is, subscribe [`ORIGINATE_EVENTS`] first or the handle never
+    /// sees the call progress or end
+    pub async fn originate(&self, mut originate: Originate) -> Result<OriginateHandle> {
+        let uuid = match originate.uuid() {
+            Some(uuid) => uuid.to_string(),
+            None => {
+                let uuid = uuid::Uuid::new_v4().to_string();
+                if let Some(group) = originate.groups.first_mut() {
+                    group
+                        .vars
+                        .push(("origination_uuid".to_string(), uuid.clone()));
+                }
+                uuid
+            }
+        };
+        // listen before the job can finish
+        let events = self.events();
+        let job_uuid = uuid::Uuid::new_v4().to_string();
+        let command = Command::bgapi(originate.to_string(), Some(job_uuid.clone()));
+        reply_result(self.execute(&command).await?)?;
+        Ok(OriginateHandle::follow(events, uuid, job_uuid))
+    }
+}
+
+fn leg(endpoint: impl Into<String>) -> Leg {
+    Leg {
+        vars: Vars::new(),
+        endpoint: endpoint.into(),
+    }
+}
+
+/// `,` and `'` are escaped, values with spaces are quoted
+fn escape(value: &str) -> String {
+    let escaped = value.replace('\'', "\\'").replace(',', "\\,");
+    quote(&escaped)
+}
+
+fn quote(value: &str) -> String {
+    if value.contains(char::is_whitespace) {
+        format!("'{}'", value)
+    } else {
+        value.to_string()
+    }
+}
+
+fn unescape(value: &str) -> String {
+    let value = value.trim();
+    let value = value
+        .strip_prefix('\'')
+        .and_then(|v| v.strip_suffix('\''))
+        .unwrap_or(value);
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(next @ (',' | '\'')) => out.push(next),
+                Some(next) => {
+                    out.push('\\');
+                    out.push(next);
+                }
+                None => out.push('\\'),
+            },
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn vars_to_string(vars: &Vars) -> String {
+    let vars: Vec<_> = vars
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, escape(value)))
+        .collect();
+    vars.join(",")
+}
+
+fn parse_vars(block: &str) -> Vars {
+    split_top(block, ",")
+        .into_iter()
+        .filter_map(|var| {
+            let (key, value) = var.split_once('=')?;
+            Some((key.trim().to_string(), unescape(value)))
+        })
+        .collect()
+}
+
+/// position of the `close` ending a block, skipping quoted and escaped parts
+fn block_end(s: &str, close: char) -> Option<usize> {
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '\'' => quoted = !quoted,
+            c if c == close && !quoted => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// split on `sep` outside of quotes, escapes and `{}`/`[]`/`<>`/`()` blocks
+fn split_top<'a>(s: &'a str, sep: &str) -> Vec<&'a str> {
+    let mut parts = Vec::new();
+    let (mut depth, mut quoted, mut escaped) = (0usize, false, false);
+    let mut start = 0;
+    let mut chars = s.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '\'' => quoted = !quoted,
+            _ if quoted => {}
+            '{' | '[' | '<' | '(' => depth += 1,
+            '}' | ']' | '>' | ')' => depth = depth.saturating_sub(1),
+            _ if depth == 0 && s[i..].starts_with(sep) => {
+                parts.push(&s[start..i]);
+                start = i + sep.len();
+                // skip the rest of a multi-char separator
+                for _ in 1..sep.chars().count() {
+                    chars.next();
+                }
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+/// split command arguments on whitespace outside of quotes and blocks,
+/// quotes around a whole argument are removed
+fn split_args(s: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let (mut depth, mut quoted, mut escaped) = (0usize, false, false);
+    let mut start = None;
+    for (i, c) in s.char_indices() {
+        if start.is_none() && !c.is_whitespace() {
+            start = Some(i);
+        }
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '\'' => quoted = !quoted,
+            _ if quoted => {}
+            '{' | '[' | '<' | '(' => depth += 1,
+            '}' | ']' | '>' | ')' => depth = depth.saturating_sub(1),
+            c if c.is_whitespace() && depth == 0 => {
+                if let Some(from) = start.take() {
+                    args.push(&s[from..i]);
+                }
+            }
+            _ => {}
+        }
+    }
+    if let Some(from) = start {
+        args.push(&s[from..]);
+    }
+    args.into_iter()
+        .map(|arg| {
+            arg.strip_prefix('\'')
+                .and_then(|a| a.strip_suffix('\''))
+                .unwrap_or(arg)
+                .to_string()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tests::{json_event, mock_freeswitch_steps},
+        Esl,
+    };
+
+    #[test]
+    fn build_dial_strings() {
+        let originate = Originate::new("user/1000")
+            .enterprise_var("ignore_early_media", "true")
+            .var("origination_uuid", "444444")
+            .var("sip_h_X-Tags", "a,b")
+            .leg_var("leg_timeout", 20)
+            .enterprise("user/1001")
+            .var("name", "it's")
+            .extension("9664")
+            .dialplan("XML")
+            .context("default")
+            .caller_id("Front Desk", "1002");
+        assert_eq!(
+            originate.to_string(),
+            "originate <ignore_early_media=true>{origination_uuid=444444,sip_h_X-Tags=a\\,b}[leg_timeout=20]user/1000\
+             :_:{name=it\\'s}user/1001 9664 XML default 'Front Desk' 1002"
+        );
+        assert_eq!(originate.uuid(), Some("444444"));
+        assert_eq!(
+            originate.to_string().parse::<Originate>().unwrap(),
+            originate
+        );
+    }
+
+    #[test]
+    fn parse_hand_written() {
+        let originate: Originate =
+            "originate [origination_caller_id_name=pc][origination_uuid=444444]user/1000 &park"
+                .parse()
+                .unwrap();
+        let leg = &originate.groups[0].legs[0][0];
+        assert_eq!(leg.endpoint, "user/1000");
+        assert_eq!(
+            leg.vars,
+            [
+                ("origination_caller_id_name".to_string(), "pc".to_string()),
+                ("origination_uuid".to_string(), "444444".to_string()),
+            ]
+        );
+        assert_eq!(originate.target, Target::default());
+
+        let originate: Originate =
+            "{a='x, y'}user/1000,user/1001|user/1002 &playback(/tmp/a b.wav)"
+                .parse()
+                .unwrap();
+        assert_eq!(originate.groups[0].vars[0].1, "x, y");
+        assert_eq!(originate.groups[0].legs.len(), 2);
+        assert_eq!(originate.groups[0].legs[0].len(), 2);
+        assert_eq!(
+            originate.target,
+            Target::App {
+                name: "playback".to_string(),
+                args: Some("/tmp/a b.wav".to_string()),
+            }
+        );
+        assert!(Originate::from_dial_string("{a=b user/1000").is_err());
+    }
+
+    #[tokio::test]
+    async fn follow_originate() {
+        let (tx, _) = broadcast::channel(16);
+        let mut handle =
+            OriginateHandle::follow(tx.subscribe(), "a-leg".to_string(), "job".to_string());
+        assert_eq!(handle.progress(), Progress::Trying);
+        tx.send(json_event(
+            serde_json::json!({"Event-Name": "CHANNEL_PROGRESS", "Unique-ID": "other"}),
+        ))
+        .unwrap();
+        tx.send(json_event(
+            serde_json::json!({"Event-Name": "CHANNEL_PROGRESS", "Unique-ID": "a-leg"}),
+        ))
+        .unwrap();
+        assert_eq!(handle.next_progress().await, Some(Progress::Ringing));
+        tx.send(json_event(
+            serde_json::json!({"Event-Name": "CHANNEL_ANSWER", "Unique-ID": "a-leg"}),
+        ))
+        .unwrap();
+        assert_eq!(handle.next_progress().await, Some(Progress::Answered));
+        tx.send(json_event(serde_json::json!({"Event-Name": "BACKGROUND_JOB", "Job-UUID": "job", "_body": "+OK a-leg\n"})))
+        .unwrap();
+        assert_eq!(handle.await.unwrap(), "a-leg");
+
+        let handle =
+            OriginateHandle::follow(tx.subscribe(), "b-leg".to_string(), "job2".to_string());
+        tx.send(json_event(serde_json::json!({"Event-Name": "BACKGROUND_JOB", "Job-UUID": "job2", "_body": "-ERR USER_BUSY\n"})))
+        .unwrap();
+        assert_eq!(
+            handle.await.unwrap_err(),
+            EslError::Hangup(HangupCause::UserBusy)
+        );
+    }
+
+    #[tokio::test]
+    async fn originate_keeps_subscription() {
+        let (addr, mut commands) = mock_freeswitch_steps(vec![(
+            1,
+            "Content-Type: command/reply\nReply-Text: +OK Job-UUID: job\n\n",
+        )])
+        .await;
+        let conn = Esl::inbound(addr, "ClueCon").await.unwrap();
+        let handle = conn
+            .originate(Originate::new("user/1000").leg_var("origination_uuid", "a-leg"))
+            .await
+            .unwrap();
+        assert_eq!(handle.uuid(), "a-leg");
+        let sent = commands.recv().await.unwrap();
+        assert!(
+            sent.starts_with("bgapi originate [origination_uuid=a-leg]user/1000 &park\n"),
+            "{}",
+            sent
+        );
+        assert!(commands.try_recv().is_err());
+    }
+}
@@ -0,0 +1,382 @@
+//! runtime independent esl protocol state machine
+//!
+//! [`Protocol`] does no io: feed it the bytes read from the socket, then drain
+//! [`Protocol::poll_transmit`] for bytes to write and [`Protocol::poll_output`]
+//! for what happened. it answers the `auth/request` handshake, matches
+//! `command/reply` and `api/response` frames to the commands that caused
+//! them, in order, and decodes events.
+//!
+//! ```
+//! use esl_rs::protocol::{Output, Protocol};
+//!
+//! let mut protocol = Protocol::new("ClueCon");
+//! protocol.feed(b"Content-Type: auth/request\n\n").unwrap();
+//! assert_eq!(&protocol.poll_transmit().unwrap()[..], b"auth ClueCon\n\n");
+//! protocol
+//!     .feed(b"Content-Type: command/reply\nReply-Text: +OK accepted\n\n")
+//!     .unwrap();
+//! assert!(matches!(protocol.poll_output(), Some(Output::Authenticated)));
+//!
+//! let id = protocol.send("api status");
+//! assert_eq!(&protocol.poll_transmit().unwrap()[..], b"api status\n\n");
+//! protocol
+//!     .feed(b"Content-Type: api/response\nContent-Length: 3\n\nUP\n")
+//!     .unwrap();
+//! match protocol.poll_output() {
+//!     Some(Output::Reply { id: reply_id, frame }) => {
+//!         assert_eq!(reply_id, id);
+//!         assert_eq!(frame.body.as_deref(), Some(&b"UP\n"[..]));
+//!     }
+//!     _ => unreachable!(),
+//! }
+//! ```
+
+use bytes::{Bytes, BytesMut};
+use std::collections::{HashSet, VecDeque};
+use tracing::{debug, warn};
+
+use crate::{
+    codec::{EslCodec, Frame},
+    command::Command,
+    error::{EslError, Result},
+    event::{Event, EventData},
+};
+
+/// identifies a command sent through [`Protocol::send`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RequestId(u64);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    /// connected, waiting for `auth/request`
+    Connecting,
+    /// `auth` sent, waiting for its reply
+    Authenticating,
+    Ready,
+    /// rejected, disconnected or broken, nothing more will be decoded
+    Closed,
+}
+
+/// what the protocol produced from the bytes fed so far
+#[derive(Debug, Clone)]
+pub enum Output {
+    Authenticated,
+    /// the handshake failed, [`EslError::AuthFailed`] or [`EslError::AclRejected`]
+    AuthFailed(EslError),
+    /// the `command/reply` or `api/response` to the command sent as `id`
+    Reply {
+        id: RequestId,
+        frame: Frame,
+    },
+    Event(Event),
+    /// `text/disconnect-notice`, freeswitch is closing the socket
+    Disconnected(Frame),
+}
+
+#[derive(Debug)]
+pub struct Protocol {
+    codec: EslCodec,
+    read_buf: BytesMut,
+    transmit: VecDeque<Bytes>,
+    /// commands queued before the handshake finished
+    held: VecDeque<(RequestId, Bytes)>,
+    /// commands written and waiting for their reply, oldest first
+    pending: VecDeque<RequestId>,
+    /// written commands whose caller gave up, their replies are dropped
+    abandoned: HashSet<RequestId>,
+    outputs: VecDeque<Output>,
+    password: String,
+    state: State,
+    next_id: u64,
+}
+
+impl Protocol {
+    pub fn new(password: impl ToString) -> Self {
+        Self {
+            codec: EslCodec::new(),
+            read_buf: BytesMut::with_capacity(64 * 1024),
+            transmit: VecDeque::new(),
+            held: VecDeque::new(),
+            pending: VecDeque::new(),
+            abandoned: HashSet::new(),
+            outputs: VecDeque::new(),
+            password: password.to_string(),
+            state: State::Connecting,
+            next_id: 0,
+        }
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.state == State::Ready
+    }
+
+    /// commands sent and not answered yet, oldest first
+    pub fn pending(&self) -> impl Iterator<Item = RequestId> + '_ {
+        self.pending.iter().copied()
+    }
+
+    /// the read buffer, to read into directly before calling [`Self::process`]
+    pub fn buffer_mut(&mut self) -> &mut BytesMut {
+        &mut self.read_buf
+    }
+
+    /// append bytes read from the socket and process them
+    pub fn feed(&mut self, data: &[u8]) -> Result<()> {
+        self.read_buf.extend_from_slice(data);
+        self.process()
+    }
+
+    /// decode every complete frame in the read buffer
+    ///
+    /// a framing error closes the protocol, the error is returned once
+    pub fn process(&mut self) -> Result<()> {
+        while self.state != State::Closed {
+            match self.codec.decode_frame(&mut self.read_buf) {
+                Ok(Some(frame)) => self.handle_frame(frame),
+                Ok(None) => break,
+                Err(e) => {
+                    self.state = State::Closed;
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// queue a command, `\n\n` is appended. commands sent before the handshake
+    /// finished are held back until it does
+    pub fn send(&mut self, command: &str) -> RequestId {
+        let mut buf = BytesMut::with_capacity(command.len() + 2);
+        buf.extend_from_slice(command.as_bytes());
+        buf.extend_from_slice(b"\n\n");
+        self.send_raw(buf.freeze())
+    }
+
+    /// queue an already terminated command
+    pub fn send_raw(&mut self, command: Bytes) -> RequestId {
+        let id = RequestId(self.next_id);
+        self.next_id += 1;
+        self.pending.push_back(id);
+        if self.is_ready() {
+            self.transmit.push_back(command);
+        } else {
+            self.held.push_back((id, command));
+        }
+        id
+    }
+
+    /// give up on a command, e.g. after a timeout
+    ///
+    /// a command not written yet is dropped. one already written stays in the
+    /// queue, because freeswitch will still answer it, but its reply is
+    /// discarded so later replies keep matching their own commands
+    pub fn cancel(&mut self, id: RequestId) {
+        if let Some(i) = self.held.iter().position(|(held, _)| *held == id) {
+            self.held.remove(i);
+            self.pending.retain(|pending| *pending != id);
+        } else if self.pending.contains(&id) {
+            self.abandoned.insert(id);
+        }
+    }
+
+    /// next bytes to write to the socket
+    pub fn poll_transmit(&mut self) -> Option<Bytes> {
+        self.transmit.pop_front()
+    }
+
+    pub fn poll_output(&mut self) -> Option<Output> {
+        self.outputs.pop_front()
+    }
+
+    /// mark the connection as gone, e.g. on eof
+    pub fn close(&mut self) {
+        self.state = State::Closed;
+    }
+
+    fn handle_frame(&mut self, frame: Frame) {
+        match (self.state, frame.content_type()) {
+            (_, Some("text/disconnect-notice")) => {
+                debug!("disconnect notice");
+                self.state = State::Closed;
+                self.outputs.push_back(Output::Disconnected(frame));
+            }
+            (_, Some("text/rude-rejection")) => {
+                self.state = State::Closed;
+                self.outputs
+                    .push_back(Output::AuthFailed(EslError::AclRejected));
+            }
+            (State::Connecting, Some("auth/request")) => {
+                match Command::auth(self.password.as_str()).encode() {
+                    Ok(auth) => {
+                        self.state = State::Authenticating;
+                        self.transmit.push_back(Bytes::from(auth));
+                    }
+                    Err(e) => {
+                        self.state = State::Closed;
+                        self.outputs.push_back(Output::AuthFailed(e));
+                    }
+                }
+            }
+            (State::Authenticating, Some("command/reply")) => {
+                if frame
+                    .header("Reply-Text")
+                    .is_some_and(|text| text.starts_with("+OK"))
+                {
+                    debug!("auth success");
+                    self.state = State::Ready;
+                    self.transmit
+                        .extend(self.held.drain(..).map(|(_, command)| command));
+                    self.outputs.push_back(Output::Authenticated);
+                } else {
+                    self.state = State::Closed;
+                    self.outputs
+                        .push_back(Output::AuthFailed(EslError::AuthFailed));
+                }
+            }
+            (State::Ready, Some("command/reply" | "api/response")) => {
+                match self.pending.pop_front() {
+                    Some(id) if self.abandoned.remove(&id) => {
+                        debug!("drop reply to abandoned command: {:?}", frame);
+                    }
+                    Some(id) => self.outputs.push_back(Output::Reply { id, frame }),
+                    None => warn!("reply without a pending command: {:?}", frame),
+                }
+            }
+            (_, Some(content_type)) if content_type.starts_with("text/event-") => {
+                self.outputs
+                    .push_back(Output::Event(EventData::from(frame).into()));
+            }
+            _ => {
+                // log/data and anything unknown still reach the event stream
+                self.outputs
+                    .push_back(Output::Event(EventData::from(frame).into()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ready() -> Protocol {
+        let mut protocol = Protocol::new("ClueCon");
+        protocol.feed(b"Content-Type: auth/request\n\n").unwrap();
+        protocol
+            .feed(b"Content-Type: command/reply\nReply-Text: +OK accepted\n\n")
+            .unwrap();
+        protocol.poll_transmit().unwrap();
+        assert!(matches!(
+            protocol.poll_output(),
+            Some(Output::Authenticated)
+        ));
+        protocol
+    }
+
+    #[test]
+    fn held_until_authenticated() {
+        let mut protocol = Protocol::new("ClueCon");
+        let id = protocol.send("event json ALL");
+        assert!(protocol.poll_transmit().is_none());
+        protocol.feed(b"Content-Type: auth/request\n\n").unwrap();
+        assert_eq!(&protocol.poll_transmit().unwrap()[..], b"auth ClueCon\n\n");
+        assert!(protocol.poll_transmit().is_none());
+        protocol
+            .feed(b"Content-Type: command/reply\nReply-Text: +OK accepted\n\n")
+            .unwrap();
+        assert_eq!(
+            &protocol.poll_transmit().unwrap()[..],
+            b"event json ALL\n\n"
+        );
+        assert_eq!(protocol.pending().collect::<Vec<_>>(), [id]);
+    }
+
+    #[test]
+    fn auth_failures() {
+        let mut protocol = Protocol::new("wrong");
+        protocol.feed(b"Content-Type: auth/request\n\n").unwrap();
+        protocol
+            .feed(b"Content-Type: command/reply\nReply-Text: -ERR invalid\n\n")
+            .unwrap();
+        assert!(matches!(
+            protocol.poll_output(),
+            Some(Output::AuthFailed(EslError::AuthFailed))
+        ));
+        assert_eq!(protocol.state(), State::Closed);
+
+        let mut protocol = Protocol::new("ClueCon");
+        protocol
+            .feed(b"Content-Type: text/rude-rejection\nContent-Length: 6\n\ndenied")
+            .unwrap();
+        assert!(matches!(
+            protocol.poll_output(),
+            Some(Output::AuthFailed(EslError::AclRejected))
+        ));
+    }
+
+    #[test]
+    fn replies_in_order_between_events() {
+        let mut protocol = ready();
+        let first = protocol.send("api status");
+        let second = protocol.send("event json ALL");
+        protocol
+            .feed(
+                b"Content-Type: api/response\nContent-Length: 3\n\nUP\n\
+                  Content-Length: 27\nContent-Type: text/event-json\n\n{\"Event-Name\":\"HEARTBEAT\"}\n\
+                  Content-Type: command/reply\nReply-Text: +OK event listener enabled json\n\n",
+            )
+            .unwrap();
+        assert!(matches!(protocol.poll_output(), Some(Output::Reply { id, .. }) if id == first));
+        assert!(matches!(
+            protocol.poll_output(),
+            Some(Output::Event(Event::Heartbeat(_)))
+        ));
+        assert!(matches!(protocol.poll_output(), Some(Output::Reply { id, .. }) if id == second));
+        assert!(protocol.poll_output().is_none());
+        assert_eq!(protocol.pending().count(), 0);
+    }
+
+    #[test]
+    fn cancelled_commands() {
+        let mut connecting = Protocol::new("ClueCon");
+        let held = connecting.send("api never");
+        connecting.cancel(held);
+        assert_eq!(connecting.pending().count(), 0);
+
+        let mut protocol = ready();
+        let slow = protocol.send("api slow");
+        let fast = protocol.send("api fast");
+        protocol.cancel(slow);
+        protocol
+            .feed(
+                b"Content-Type: api/response\nContent-Length: 4\n\nslow\
+                  Content-Type: api/response\nContent-Length: 4\n\nfast",
+            )
+            .unwrap();
+        match protocol.poll_output() {
+            Some(Output::Reply { id, frame }) => {
+                assert_eq!(id, fast);
+                assert_eq!(frame.body.as_deref(), Some(&b"fast"[..]));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(protocol.poll_output().is_none());
+    }
+
+    #[test]
+    fn disconnect_notice() {
+        let mut protocol = ready();
+        protocol
+            .feed(b"Content-Type: text/disconnect-notice\nContent-Length: 3\n\nbye")
+            .unwrap();
+        assert!(matches!(
+            protocol.poll_output(),
+            Some(Output::Disconnected(_))
+        ));
+        assert_eq!(protocol.state(), State::Closed);
+    }
+}
@@ -0,0 +1,171 @@
+//! `+OK`/`-ERR`/`-USAGE` replies
+//!
+//! socket commands answer with a `Reply-Text` header, api commands with an
+//! `api/response` body. both use the same conventions, [`CommandReply`] reads
+//! either.
+
+use crate::{
+    codec::Frame,
+    error::{EslError, Result},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandReply {
+    /// `+OK <text>`, or any api output that is not an error
+    Ok(String),
+    /// `-ERR <reason>`
+    Err(String),
+    /// `-USAGE: <usage>`
+    Usage(String),
+}
+
+impl CommandReply {
+    pub fn parse(text: &str) -> Self {
+        let text = text.trim();
+        if let Some(reason) = text.strip_prefix("-ERR") {
+            Self::Err(reason.trim().to_string())
+        } else if let Some(usage) = text.strip_prefix("-USAGE") {
+            Self::Usage(usage.trim_start_matches(':').trim().to_string())
+        } else if let Some(text) = text.strip_prefix("+OK") {
+            Self::Ok(text.trim().to_string())
+        } else {
+            Self::Ok(text.to_string())
+        }
+    }
+
+    pub fn is_ok(&self) -> bool {
+        matches!(self, Self::Ok(_))
+    }
+
+    /// the text after the `+OK`/`-ERR`/`-USAGE` marker
+    pub fn text(&self) -> &str {
+        match self {
+            Self::Ok(text) | Self::Err(text) | Self::Usage(text) => text,
+        }
+    }
+
+    /// a `Key: value` pair carried in the reply text, e.g. `Job-UUID`
+    pub fn value(&self, key: &str) -> Option<&str> {
+        let text = self.text();
+        let start = text.find(key)? + key.len();
+        let value = text[start..].strip_prefix(':')?.trim_start();
+        Some(value.split_whitespace().next().unwrap_or_default())
+    }
+
+    /// `+OK Job-UUID: <uuid>`, the reply to `bgapi`
+    pub fn job_uuid(&self) -> Option<&str> {
+        self.value("Job-UUID")
+    }
+
+    /// the `+OK` text, or the error freeswitch reported
+    pub fn into_result(self) -> Result<String> {
+        match self {
+            Self::Ok(text) => Ok(text),
+            Self::Err(reason) => Err(EslError::from_reason(reason)),
+            Self::Usage(usage) => Err(EslError::Usage(usage)),
+        }
+    }
+}
+
+impl From<&Frame> for CommandReply {
+    /// `Reply-Text` for `command/reply`, the body for everything else
+    fn from(frame: &Frame) -> Self {
+        match frame.header("Reply-Text") {
+            Some(text) => Self::parse(text),
+            None => Self::parse(&String::from_utf8_lossy(
+                frame.body.as_deref().unwrap_or_default(),
+            )),
+        }
+    }
+}
+
+impl EslError {
+    /// map a `-ERR` reason onto the error callers usually want to match on
+    pub fn from_reason(reason: impl Into<String>) -> Self {
+        let reason = reason.into();
+        let lower = reason.to_ascii_lowercase();
+        if lower.contains("no such channel") {
+            Self::NoSuchChannel
+        } else if lower.contains("permission denied") {
+            Self::PermissionDenied
+        } else if lower.contains("invalid command") || lower.contains("command not found") {
+            Self::InvalidCommand(reason)
+        } else {
+            Self::ApiError(reason)
+        }
+    }
+}
+
+/// the output of an `api` command, `-ERR` and `-USAGE` outputs are errors
+pub(crate) fn api_result(frame: Frame) -> Result<String> {
+    CommandReply::from(&frame).into_result()?;
+    Ok(frame
+        .body
+        .map(|body| String::from_utf8_lossy(&body).into_owned())
+        .unwrap_or_default())
+}
+
+/// a `command/reply`, `-ERR` replies are errors
+pub(crate) fn reply_result(frame: Frame) -> Result<CommandReply> {
+    let reply = CommandReply::from(&frame);
+    reply.clone().into_result()?;
+    Ok(reply)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_replies() {
+        let reply = CommandReply::parse("+OK Job-UUID: 7f4d-11 \n");
+        assert!(reply.is_ok());
+        assert_eq!(reply.job_uuid(), Some("7f4d-11"));
+        assert_eq!(
+            CommandReply::parse("+OK 1d0b"),
+            CommandReply::Ok("1d0b".to_string())
+        );
+        assert_eq!(
+            CommandReply::parse("UP 0 years, 2 days\n"),
+            CommandReply::Ok("UP 0 years, 2 days".to_string())
+        );
+        assert_eq!(
+            CommandReply::parse("-USAGE: <uuid> <var>\n"),
+            CommandReply::Usage("<uuid> <var>".to_string())
+        );
+        assert_eq!(CommandReply::parse("+OK").value("Job-UUID"), None);
+    }
+
+    #[test]
+    fn map_errors() {
+        let err = |text: &str| CommandReply::parse(text).into_result().unwrap_err();
+        assert_eq!(err("-ERR No such channel!\n"), EslError::NoSuchChannel);
+        assert_eq!(err("-ERR permission denied"), EslError::PermissionDenied);
+        assert_eq!(
+            err("-ERR nope Command not found!\n"),
+            EslError::InvalidCommand("nope Command not found!".to_string())
+        );
+        assert_eq!(
+            err("-ERR USER_BUSY"),
+            EslError::ApiError("USER_BUSY".to_string())
+        );
+        assert_eq!(err("-USAGE: <uuid>"), EslError::Usage("<uuid>".to_string()));
+    }
+
+    #[test]
+    fn from_frames() {
+        let mut frame = Frame::default();
+        frame
+            .headers
+            .insert("Reply-Text".to_string(), "-ERR invalid command".to_string());
+        assert_eq!(
+            CommandReply::from(&frame),
+            CommandReply::Err("invalid command".to_string())
+        );
+        let frame = Frame {
+            body: Some("+OK bridged\n".into()),
+            ..Default::default()
+        };
+        assert_eq!(api_result(frame).unwrap(), "+OK bridged\n");
+    }
+}
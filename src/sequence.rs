@@ -0,0 +1,337 @@
+//! `Event-Sequence` tracking
+//!
+//! freeswitch stamps every event it fires with a counter that is global to the
+//! core (`Core-UUID`). a connection subscribed to all events therefore sees a
+//! contiguous sequence, and any hole means events were lost on the way, e.g.
+//! because the socket or the event channel backed up.
+//! with a filtered subscription holes are expected, so tracking is opt-in via
+//! [`InboundOptions::track_sequence`](crate::InboundOptions::track_sequence).
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::{Duration, Instant},
+};
+
+use crate::event::Event;
+
+/// a hole in the event sequence of one freeswitch core
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SequenceGap {
+    pub core_uuid: String,
+    /// the sequence number that should have come next
+    pub expected: u64,
+    /// the sequence number that came instead
+    pub received: u64,
+}
+
+impl SequenceGap {
+    /// how many events were lost
+    pub fn missing(&self) -> u64 {
+        self.received - self.expected
+    }
+}
+
+/// counters kept by a [`SequenceTracker`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SequenceStats {
+    /// events carrying an `Event-Sequence`
+    pub received: u64,
+    /// events delivered out of order by freeswitch and put back in place
+    pub reordered: u64,
+    /// events that arrived after their slot was given up, or twice
+    pub late: u64,
+    pub gaps: u64,
+    pub missing: u64,
+    /// events the [`crate::conn::Conn::handle`] queue had no room for before a
+    /// handler was attached, counted with or without tracking
+    pub dropped: u64,
+}
+
+/// output of a [`SequenceTracker`], in delivery order
+#[derive(Debug, Clone)]
+pub enum Sequenced {
+    Event(Event),
+    Gap(SequenceGap),
+}
+
+/// how long an event is held back by default, see [`SequenceTracker::with_max_hold`]
+pub const DEFAULT_MAX_HOLD: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Default)]
+struct CoreState {
+    next: Option<u64>,
+    /// held back events and when they arrived
+    pending: BTreeMap<u64, (Instant, Event)>,
+}
+
+/// detects gaps per `Core-UUID` and restores order within a small window
+///
+/// with a window of `0` events pass straight through and a gap is reported as
+/// soon as a sequence number is skipped. with a window of `n` up to `n` events
+/// are held back waiting for the missing ones before the gap is reported.
+/// an event is never held longer than the max hold, a quiet switch may not
+/// send the next one for minutes; call [`Self::expire`] by
+/// [`Self::next_deadline`] to release it on time.
+#[derive(Debug)]
+pub struct SequenceTracker {
+    window: usize,
+    max_hold: Duration,
+    cores: HashMap<String, CoreState>,
+    stats: SequenceStats,
+}
+
+impl Default for SequenceTracker {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl SequenceTracker {
+    pub fn new(reorder_window: usize) -> Self {
+        Self {
+            window: reorder_window,
+            max_hold: DEFAULT_MAX_HOLD,
+            cores: HashMap::new(),
+            stats: SequenceStats::default(),
+        }
+    }
+
+    /// give up on a missing event once the one after it waited `max_hold`
+    pub fn with_max_hold(mut self, max_hold: Duration) -> Self {
+        self.max_hold = max_hold;
+        self
+    }
+
+    pub fn stats(&self) -> &SequenceStats {
+        &self.stats
+    }
+
+    /// feed an event, returns what can be delivered now
+    pub fn push(&mut self, evt: Event) -> Vec<Sequenced> {
+        self.push_at(evt, Instant::now())
+    }
+
+    fn push_at(&mut self, evt: Event, now: Instant) -> Vec<Sequenced> {
+        let mut out = Vec::new();
+        let sequence = evt
+            .get_body_by_key("Event-Sequence")
+            .and_then(|s| s.trim().parse::<u64>().ok());
+        let (Some(sequence), Some(core_uuid)) = (sequence, evt.get_body_by_key("Core-UUID")) else {
+            out.push(Sequenced::Event(evt));
+            return out;
+        };
+        self.stats.received += 1;
+
+        // a new core means freeswitch restarted, nothing more will come from the old one
+        if !self.cores.contains_key(&core_uuid) {
+            self.flush_into(&mut out);
+            self.cores.clear();
+        }
+        let core = self.cores.entry(core_uuid.clone()).or_default();
+        let next = *core.next.get_or_insert(sequence);
+
+        if sequence < next || core.pending.contains_key(&sequence) {
+            self.stats.late += 1;
+            out.push(Sequenced::Event(evt));
+            return out;
+        }
+        // the slot everything held back was waiting for
+        if sequence == next && !core.pending.is_empty() {
+            self.stats.reordered += 1;
+        }
+        core.pending.insert(sequence, (now, evt));
+        Self::release(
+            core,
+            &core_uuid,
+            self.window,
+            None,
+            &mut self.stats,
+            &mut out,
+        );
+        self.expire_into(now, &mut out);
+        out
+    }
+
+    /// when the longest held event is due, `None` when nothing is held
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.cores
+            .values()
+            .flat_map(|core| core.pending.values())
+            .map(|(held, _)| *held + self.max_hold)
+            .min()
+    }
+
+    /// deliver the events held for longer than the max hold, reporting the
+    /// gaps before them
+    pub fn expire(&mut self, now: Instant) -> Vec<Sequenced> {
+        let mut out = Vec::new();
+        self.expire_into(now, &mut out);
+        out
+    }
+
+    fn expire_into(&mut self, now: Instant, out: &mut Vec<Sequenced>) {
+        for (core_uuid, core) in self.cores.iter_mut() {
+            let due = core
+                .pending
+                .iter()
+                .rev()
+                .find(|(_, (held, _))| now.duration_since(*held) >= self.max_hold)
+                .map(|(sequence, _)| *sequence);
+            if due.is_some() {
+                Self::release(core, core_uuid, self.window, due, &mut self.stats, out);
+            }
+        }
+    }
+
+    /// give up waiting and deliver everything held back
+    pub fn flush(&mut self) -> Vec<Sequenced> {
+        let mut out = Vec::new();
+        self.flush_into(&mut out);
+        out
+    }
+
+    fn flush_into(&mut self, out: &mut Vec<Sequenced>) {
+        for (core_uuid, core) in self.cores.iter_mut() {
+            Self::release(core, core_uuid, 0, None, &mut self.stats, out);
+        }
+    }
+
+    /// deliver what is in order, then give up on the missing events while
+    /// more than `window` are held or the first held one is at most `through`
+    fn release(
+        core: &mut CoreState,
+        core_uuid: &str,
+        window: usize,
+        through: Option<u64>,
+        stats: &mut SequenceStats,
+        out: &mut Vec<Sequenced>,
+    ) {
+        let Some(mut next) = core.next else {
+            return;
+        };
+        loop {
+            if let Some((_, evt)) = core.pending.remove(&next) {
+                out.push(Sequenced::Event(evt));
+                next += 1;
+                continue;
+            }
+            let due = matches!(
+                (core.pending.keys().next(), through),
+                (Some(first), Some(through)) if *first <= through
+            );
+            if core.pending.len() <= window && !due {
+                break;
+            }
+            let Some((received, (_, evt))) = core.pending.pop_first() else {
+                break;
+            };
+            let gap = SequenceGap {
+                core_uuid: core_uuid.to_string(),
+                expected: next,
+                received,
+            };
+            stats.gaps += 1;
+            stats.missing += gap.missing();
+            out.push(Sequenced::Gap(gap));
+            out.push(Sequenced::Event(evt));
+            next = received + 1;
+        }
+        core.next = Some(next);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::json_event;
+
+    fn event(core: &str, sequence: u64) -> Event {
+        let body = serde_json::json!({
+            "Event-Name": "HEARTBEAT",
+            "Core-UUID": core,
+            "Event-Sequence": sequence.to_string(),
+        });
+        json_event(body)
+    }
+
+    fn summary(out: &[Sequenced]) -> Vec<String> {
+        out.iter()
+            .map(|s| match s {
+                Sequenced::Event(evt) => evt.get_body_by_key("Event-Sequence").unwrap(),
+                Sequenced::Gap(gap) => format!("gap {}..{}", gap.expected, gap.received),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn detect_gaps() {
+        let mut tracker = SequenceTracker::new(0);
+        let mut out = Vec::new();
+        for seq in [1, 2, 5, 6, 4] {
+            out.extend(tracker.push(event("a", seq)));
+        }
+        assert_eq!(summary(&out), ["1", "2", "gap 3..5", "5", "6", "4"]);
+        assert_eq!(
+            tracker.stats(),
+            &SequenceStats {
+                received: 5,
+                reordered: 0,
+                late: 1,
+                gaps: 1,
+                missing: 2,
+                dropped: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn reorder_window() {
+        let mut tracker = SequenceTracker::new(2);
+        let mut out = Vec::new();
+        for seq in [1, 3, 2, 5, 6, 7] {
+            out.extend(tracker.push(event("a", seq)));
+        }
+        assert_eq!(summary(&out), ["1", "2", "3", "gap 4..5", "5", "6", "7"]);
+        assert_eq!(tracker.stats().gaps, 1);
+        assert!(tracker.flush().is_empty());
+    }
+
+    #[test]
+    fn max_hold() {
+        let mut tracker = SequenceTracker::new(4).with_max_hold(Duration::from_millis(100));
+        let start = Instant::now();
+        let mut out = Vec::new();
+        out.extend(tracker.push_at(event("a", 1), start));
+        out.extend(tracker.push_at(event("a", 3), start));
+        out.extend(tracker.push_at(event("a", 5), start + Duration::from_millis(50)));
+        assert_eq!(summary(&out), ["1"]);
+        assert_eq!(
+            tracker.next_deadline(),
+            Some(start + Duration::from_millis(100))
+        );
+
+        // nothing more arrives, the timer releases what is due
+        out.extend(tracker.expire(start + Duration::from_millis(100)));
+        assert_eq!(summary(&out), ["1", "gap 2..3", "3"]);
+        out.extend(tracker.expire(start + Duration::from_millis(120)));
+        assert_eq!(summary(&out), ["1", "gap 2..3", "3"]);
+
+        // or the next push does
+        out.extend(tracker.push_at(event("a", 7), start + Duration::from_millis(150)));
+        assert_eq!(summary(&out), ["1", "gap 2..3", "3", "gap 4..5", "5"]);
+        assert_eq!(
+            tracker.next_deadline(),
+            Some(start + Duration::from_millis(250))
+        );
+    }
+
+    #[test]
+    fn core_restart() {
+        let mut tracker = SequenceTracker::new(4);
+        let mut out = Vec::new();
+        for (core, seq) in [("a", 10), ("a", 12), ("b", 1), ("b", 2)] {
+            out.extend(tracker.push(event(core, seq)));
+        }
+        assert_eq!(summary(&out), ["10", "gap 11..12", "12", "1", "2"]);
+    }
+}
@@ -0,0 +1,230 @@
+//! typed results of `show ... as json` and `uuid_dump`
+//!
+//! `show` answers `{"row_count":2,"rows":[{...},{...}]}`, with `rows` left out
+//! when there are none. every column is a string, numbers are coerced as in
+//! [`crate::de`] and columns without a field are ignored.
+
+use std::collections::HashMap;
+
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::Value;
+
+use crate::{
+    command::Command,
+    conn::Conn,
+    de::{from_fields, FieldValue},
+    error::{EslError, Result},
+    event::EventData,
+    reply::api_result,
+};
+
+/// a row of `show channels`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct ChannelRow {
+    pub uuid: String,
+    pub direction: String,
+    pub created: String,
+    pub created_epoch: Option<u64>,
+    pub name: String,
+    pub state: String,
+    pub cid_name: String,
+    pub cid_num: String,
+    pub ip_addr: String,
+    pub dest: String,
+    pub application: String,
+    pub application_data: String,
+    pub dialplan: String,
+    pub context: String,
+    pub read_codec: String,
+    pub read_rate: Option<u32>,
+    pub write_codec: String,
+    pub write_rate: Option<u32>,
+    pub secure: String,
+    pub hostname: String,
+    pub presence_id: String,
+    pub accountcode: String,
+    pub callstate: String,
+    pub callee_name: String,
+    pub callee_num: String,
+    pub callee_direction: String,
+    pub call_uuid: String,
+    pub initial_cid_name: String,
+    pub initial_cid_num: String,
+    pub initial_dest: String,
+}
+
+/// a row of `show calls`, the a-leg and the `b_` prefixed b-leg
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct CallRow {
+    pub uuid: String,
+    pub direction: String,
+    pub created: String,
+    pub created_epoch: Option<u64>,
+    pub name: String,
+    pub state: String,
+    pub cid_name: String,
+    pub cid_num: String,
+    pub ip_addr: String,
+    pub dest: String,
+    pub callstate: String,
+    pub call_uuid: String,
+    pub hostname: String,
+    pub b_uuid: String,
+    pub b_direction: String,
+    pub b_created: String,
+    pub b_created_epoch: Option<u64>,
+    pub b_name: String,
+    pub b_state: String,
+    pub b_cid_name: String,
+    pub b_cid_num: String,
+    pub b_ip_addr: String,
+    pub b_dest: String,
+    pub b_callstate: String,
+    pub call_created_epoch: Option<u64>,
+}
+
+/// a row of `show registrations`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct RegistrationRow {
+    pub reg_user: String,
+    pub realm: String,
+    pub token: String,
+    pub url: String,
+    pub expires: Option<u64>,
+    pub network_ip: String,
+    pub network_port: Option<u16>,
+    pub network_proto: String,
+    pub hostname: String,
+    pub metadata: String,
+}
+
+/// the rows of a `show ... as json` output
+pub fn parse_show<T: DeserializeOwned>(text: &str) -> Result<Vec<T>> {
+    let value: Value = serde_json::from_str(text.trim())
+        .map_err(|e| EslError::DeserializeError(format!("show output: {}", e)))?;
+    let rows = match value.get("rows") {
+        Some(Value::Array(rows)) => rows,
+        // no rows at all
+        _ if value.get("row_count").is_some() => return Ok(Vec::new()),
+        _ => {
+            return Err(EslError::DeserializeError(
+                "show output without row_count".to_string(),
+            ))
+        }
+    };
+    rows.iter()
+        .map(|row| match row {
+            Value::Object(row) => {
+                from_fields(row.iter().map(|(k, v)| (k.as_str(), FieldValue::Json(v))))
+            }
+            _ => Err(EslError::DeserializeError(format!("show row {}", row))),
+        })
+        .collect()
+}
+
+/// parse `uuid_dump` output, either `json` or the default url-encoded
+/// `Key: Value` lines
+pub fn parse_dump(text: &str) -> Result<EventData> {
+    let text = text.trim();
+    let body = if text.starts_with('{') {
+        serde_json::from_str::<HashMap<String, Value>>(text)?
+    } else {
+        text.lines()
+            .filter_map(|line| line.split_once(": "))
+            .map(|(key, value)| (key.to_string(), Value::String(url_decode(value))))
+            .collect()
+    };
+    if body.is_empty() {
+        return Err(EslError::DeserializeError("empty uuid_dump".to_string()));
+    }
+    Ok(EventData {
+        body: Some(body),
+        ..Default::default()
+    })
+}
+
+/// `%XX` decoding as freeswitch encodes plain event headers
+fn url_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = (bytes[i] == b'%')
+            .then(|| value.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match hex {
+            Some(b) => {
+                out.push(b);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+impl Conn {
+    /// `show <what> as json`, e.g. `show("channels like 1000")`
+    pub async fn show<T: DeserializeOwned>(&self, what: &str) -> Result<Vec<T>> {
+        let command = Command::api(format!("show {} as json", what));
+        parse_show(&api_result(self.execute(&command).await?)?)
+    }
+
+    pub async fn show_channels(&self) -> Result<Vec<ChannelRow>> {
+        self.show("channels").await
+    }
+
+    pub async fn show_calls(&self) -> Result<Vec<CallRow>> {
+        self.show("calls").await
+    }
+
+    pub async fn show_registrations(&self) -> Result<Vec<RegistrationRow>> {
+        self.show("registrations").await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn show_rows() {
+        let rows: Vec<ChannelRow> = parse_show(
+            r#"{"row_count":1,"rows":[{"uuid":"7f4d","direction":"inbound",
+                "created_epoch":"1700000000","name":"sofia/internal/1000@10.0.0.1",
+                "state":"CS_EXECUTE","read_rate":"","some_new_column":"x"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].uuid, "7f4d");
+        assert_eq!(rows[0].created_epoch, Some(1_700_000_000));
+        assert_eq!(rows[0].read_rate, None);
+
+        let rows: Vec<RegistrationRow> = parse_show("{\"row_count\":0}\n").unwrap();
+        assert!(rows.is_empty());
+        assert!(parse_show::<CallRow>("-ERR no reply").is_err());
+    }
+
+    #[test]
+    fn dumps() {
+        let plain = parse_dump(
+            "Event-Name: CHANNEL_DATA\nUnique-ID: 7f4d\nvariable_sip_from_display: John%20Doe\n",
+        )
+        .unwrap();
+        assert_eq!(plain.get_event_name().as_deref(), Some("CHANNEL_DATA"));
+        assert_eq!(
+            plain.get_var("sip_from_display").as_deref(),
+            Some("John Doe")
+        );
+
+        let json = parse_dump(r#"{"Event-Name":"CHANNEL_DATA","Unique-ID":"7f4d"}"#).unwrap();
+        assert_eq!(json.get_body_by_key("Unique-ID").as_deref(), Some("7f4d"));
+    }
+}
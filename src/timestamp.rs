@@ -0,0 +1,161 @@
+//! typed access to the microsecond timestamps freeswitch puts on events
+//!
+//! a value of `0` means the moment never happened (e.g. an unanswered call),
+//! so those accessors return `None`.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::event::EventData;
+
+/// convert a microsecond unix timestamp, `0` means unset
+pub(crate) fn from_micros(micros: &str) -> Option<SystemTime> {
+    match micros.trim().parse::<u64>().ok()? {
+        0 => None,
+        micros => Some(UNIX_EPOCH + Duration::from_micros(micros)),
+    }
+}
+
+impl EventData {
+    /// a microsecond timestamp header, `None` when missing, unset or malformed
+    pub fn get_timestamp(&self, key: &str) -> Option<SystemTime> {
+        self.get_body_by_key(key)
+            .or_else(|| self.get_header(key))
+            .and_then(|v| from_micros(&v))
+    }
+
+    /// a microsecond duration header such as `Caller-Channel-Hold-Accum`
+    pub fn get_duration(&self, key: &str) -> Option<Duration> {
+        self.get_body_by_key(key)
+            .or_else(|| self.get_header(key))
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .map(Duration::from_micros)
+    }
+
+    /// when freeswitch fired the event, `Event-Date-Timestamp`
+    pub fn event_time(&self) -> Option<SystemTime> {
+        self.get_timestamp("Event-Date-Timestamp")
+    }
+
+    pub fn created_time(&self) -> Option<SystemTime> {
+        self.get_timestamp("Caller-Channel-Created-Time")
+    }
+
+    pub fn profile_created_time(&self) -> Option<SystemTime> {
+        self.get_timestamp("Caller-Profile-Created-Time")
+    }
+
+    pub fn progress_time(&self) -> Option<SystemTime> {
+        self.get_timestamp("Caller-Channel-Progress-Time")
+    }
+
+    pub fn progress_media_time(&self) -> Option<SystemTime> {
+        self.get_timestamp("Caller-Channel-Progress-Media-Time")
+    }
+
+    pub fn answered_time(&self) -> Option<SystemTime> {
+        self.get_timestamp("Caller-Channel-Answered-Time")
+    }
+
+    pub fn bridged_time(&self) -> Option<SystemTime> {
+        self.get_timestamp("Caller-Channel-Bridged-Time")
+    }
+
+    pub fn transfer_time(&self) -> Option<SystemTime> {
+        self.get_timestamp("Caller-Channel-Transfer-Time")
+    }
+
+    pub fn hangup_time(&self) -> Option<SystemTime> {
+        self.get_timestamp("Caller-Channel-Hangup-Time")
+    }
+
+    pub fn last_hold_time(&self) -> Option<SystemTime> {
+        self.get_timestamp("Caller-Channel-Last-Hold")
+    }
+
+    /// total time spent on hold, `Caller-Channel-Hold-Accum`
+    pub fn hold_time(&self) -> Option<Duration> {
+        self.get_duration("Caller-Channel-Hold-Accum")
+    }
+
+    /// from channel creation until answer, or until hangup if never answered
+    pub fn ring_time(&self) -> Option<Duration> {
+        let end = self.answered_time().or_else(|| self.hangup_time())?;
+        end.duration_since(self.created_time()?).ok()
+    }
+
+    /// from channel creation until the first progress (180/183)
+    pub fn progress_delay(&self) -> Option<Duration> {
+        let progress = match (self.progress_time(), self.progress_media_time()) {
+            (Some(a), Some(b)) => a.min(b),
+            (a, b) => a.or(b)?,
+        };
+        progress.duration_since(self.created_time()?).ok()
+    }
+
+    /// from answer until hangup, `None` for unanswered calls
+    pub fn talk_time(&self) -> Option<Duration> {
+        self.hangup_time()?
+            .duration_since(self.answered_time()?)
+            .ok()
+    }
+
+    /// from channel creation until hangup
+    pub fn total_duration(&self) -> Option<Duration> {
+        self.hangup_time()?
+            .duration_since(self.created_time()?)
+            .ok()
+    }
+
+    #[cfg(feature = "chrono")]
+    pub fn get_datetime(&self, key: &str) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.get_timestamp(key).map(Into::into)
+    }
+
+    #[cfg(feature = "time")]
+    pub fn get_offset_datetime(&self, key: &str) -> Option<time::OffsetDateTime> {
+        self.get_timestamp(key).map(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::json_event;
+
+    fn hangup_complete(answered: &str) -> crate::event::Event {
+        let body = serde_json::json!({
+            "Event-Name": "CHANNEL_HANGUP_COMPLETE",
+            "Event-Date-Timestamp": "1700000030000000",
+            "Caller-Channel-Created-Time": "1700000000000000",
+            "Caller-Channel-Progress-Time": "1700000002500000",
+            "Caller-Channel-Progress-Media-Time": "0",
+            "Caller-Channel-Answered-Time": answered,
+            "Caller-Channel-Hangup-Time": "1700000030000000",
+            "Caller-Channel-Hold-Accum": "1500000",
+        });
+        json_event(body)
+    }
+
+    #[test]
+    fn answered_call() {
+        let data = hangup_complete("1700000010000000");
+        assert_eq!(
+            data.event_time(),
+            Some(UNIX_EPOCH + Duration::from_secs(1_700_000_030))
+        );
+        assert_eq!(data.progress_media_time(), None);
+        assert_eq!(data.progress_delay(), Some(Duration::from_millis(2500)));
+        assert_eq!(data.ring_time(), Some(Duration::from_secs(10)));
+        assert_eq!(data.talk_time(), Some(Duration::from_secs(20)));
+        assert_eq!(data.total_duration(), Some(Duration::from_secs(30)));
+        assert_eq!(data.hold_time(), Some(Duration::from_millis(1500)));
+    }
+
+    #[test]
+    fn unanswered_call() {
+        let data = hangup_complete("0");
+        assert_eq!(data.answered_time(), None);
+        assert_eq!(data.ring_time(), Some(Duration::from_secs(30)));
+        assert_eq!(data.talk_time(), None);
+    }
+}
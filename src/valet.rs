@@ -0,0 +1,230 @@
+//! valet parking: `valet_info` and `valet_parking::info` events
+//!
+//! [`ValetLots`] follows the events, subscribe them with [`VALET_EVENTS`],
+//! and keeps which extension of which lot holds which channel.
+//!
+//! ```no_run
+//! # async fn demo(conn: esl_rs::conn::Conn) -> esl_rs::error::Result<()> {
+//! use esl_rs::valet::ValetLots;
+//!
+//! let mut lots = ValetLots::new();
+//! lots.load(conn.valet_info(None).await?);
+//! for (extension, uuid) in lots.spots("my_lot") {
+//!     println!("{} holds {}", extension, uuid);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{
+    channel::token, command::Command, conn::Conn, error::Result, event::Event, reply::api_result,
+    xml::elements,
+};
+
+/// what to subscribe for [`ValetLots`]
+pub const VALET_EVENTS: [&str; 2] = ["CUSTOM", "valet_parking::info"];
+
+const SUBCLASS: &str = "valet_parking::info";
+
+/// a parked channel
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValetSpot {
+    pub extension: String,
+    pub uuid: String,
+}
+
+/// a lot from `valet_info`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValetLot {
+    pub name: String,
+    pub spots: Vec<ValetSpot>,
+}
+
+/// parse the xml of `valet_info`
+pub fn parse_valet_info(xml: &str) -> Vec<ValetLot> {
+    let mut lots: Vec<ValetLot> = Vec::new();
+    for element in elements(xml) {
+        match element.name.as_str() {
+            "lot" => lots.push(ValetLot {
+                name: element.attr("name"),
+                spots: Vec::new(),
+            }),
+            "extension" => {
+                if let Some(lot) = lots.last_mut() {
+                    lot.spots.push(ValetSpot {
+                        uuid: element.attr("uuid"),
+                        extension: element.text,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    lots
+}
+
+impl Conn {
+    /// `valet_info`, of every lot or just `lot`
+    pub async fn valet_info(&self, lot: Option<&str>) -> Result<Vec<ValetLot>> {
+        let line = match lot {
+            Some(lot) => format!("valet_info {}", token(lot)?),
+            None => "valet_info".to_string(),
+        };
+        let xml = api_result(self.execute(&Command::api(line)).await?)?;
+        Ok(parse_valet_info(&xml))
+    }
+}
+
+/// `Action` of a `valet_parking::info` event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValetAction {
+    /// a channel was parked
+    Hold,
+    /// a parked channel was picked up
+    Bridge,
+    /// a parked channel left, e.g. hung up or timed out
+    Exit,
+    Other(String),
+}
+
+/// a `valet_parking::info` event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValetEvent {
+    pub lot: String,
+    pub extension: String,
+    pub action: ValetAction,
+    /// the parked channel
+    pub uuid: Option<String>,
+}
+
+impl ValetEvent {
+    /// `None` for events that are not `valet_parking::info`
+    pub fn from_event(evt: &Event) -> Option<Self> {
+        let Event::Custom(data) = evt else {
+            return None;
+        };
+        if data.get_subclass().as_deref() != Some(SUBCLASS) {
+            return None;
+        }
+        let action = match data.get_body_by_key("Action")?.as_str() {
+            "hold" => ValetAction::Hold,
+            "bridge" => ValetAction::Bridge,
+            "exit" => ValetAction::Exit,
+            other => ValetAction::Other(other.to_string()),
+        };
+        Some(Self {
+            lot: data.get_body_by_key("Valet-Lot-Name")?,
+            extension: data.get_body_by_key("Valet-Extension").unwrap_or_default(),
+            action,
+            uuid: data.get_body_by_key("Unique-ID"),
+        })
+    }
+}
+
+/// parked channels by lot and extension
+#[derive(Debug, Default)]
+pub struct ValetLots {
+    lots: HashMap<String, BTreeMap<String, String>>,
+}
+
+impl ValetLots {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// seed from [`Conn::valet_info`], replacing the lots it lists
+    pub fn load(&mut self, lots: Vec<ValetLot>) {
+        for lot in lots {
+            self.lots.insert(
+                lot.name,
+                lot.spots
+                    .into_iter()
+                    .map(|spot| (spot.extension, spot.uuid))
+                    .collect(),
+            );
+        }
+    }
+
+    /// `(extension, uuid)` of the parked channels in `lot`
+    pub fn spots(&self, lot: &str) -> impl Iterator<Item = (&str, &str)> {
+        self.lots
+            .get(lot)
+            .into_iter()
+            .flatten()
+            .map(|(extension, uuid)| (extension.as_str(), uuid.as_str()))
+    }
+
+    /// the channel parked at `extension`
+    pub fn get(&self, lot: &str, extension: &str) -> Option<&str> {
+        self.lots.get(lot)?.get(extension).map(String::as_str)
+    }
+
+    pub fn lots(&self) -> impl Iterator<Item = &str> {
+        self.lots.keys().map(String::as_str)
+    }
+
+    /// update from one event, other events are ignored
+    pub fn push(&mut self, evt: &Event) -> Option<ValetEvent> {
+        let event = ValetEvent::from_event(evt)?;
+        let lot = self.lots.entry(event.lot.clone()).or_default();
+        match event.action {
+            ValetAction::Hold => {
+                if let Some(uuid) = &event.uuid {
+                    lot.insert(event.extension.clone(), uuid.clone());
+                }
+            }
+            ValetAction::Bridge | ValetAction::Exit => {
+                lot.remove(&event.extension);
+            }
+            ValetAction::Other(_) => {}
+        }
+        Some(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::json_event;
+
+    #[test]
+    fn track_lots() {
+        let lots = parse_valet_info(
+            "<lots>\n  <lot name=\"my_lot\">\n    <extension uuid=\"a\">6001</extension>\n  </lot>\n</lots>\n",
+        );
+        assert_eq!(lots[0].spots[0].extension, "6001");
+        let mut tracker = ValetLots::new();
+        tracker.load(lots);
+
+        let event = |action: &str, extension: &str, uuid: Option<&str>| -> Event {
+            let mut body = serde_json::json!({
+                "Event-Name": "CUSTOM",
+                "Event-Subclass": "valet_parking::info",
+                "Valet-Lot-Name": "my_lot",
+                "Valet-Extension": extension,
+                "Action": action,
+            });
+            if let Some(uuid) = uuid {
+                body["Unique-ID"] = uuid.into();
+            }
+            json_event(body)
+        };
+        tracker.push(&event("hold", "6002", Some("b")));
+        assert_eq!(tracker.get("my_lot", "6002"), Some("b"));
+        tracker.push(&event("bridge", "6001", Some("a")));
+        let spots: Vec<_> = tracker.spots("my_lot").collect();
+        assert_eq!(spots, [("6002", "b")]);
+
+        // a hold without the channel is reported but holds nothing
+        let hold = tracker.push(&event("hold", "6003", None)).unwrap();
+        assert_eq!((hold.action, hold.uuid), (ValetAction::Hold, None));
+        assert_eq!(tracker.get("my_lot", "6003"), None);
+
+        // b hung up while parked
+        let exit = tracker.push(&event("exit", "6002", Some("b"))).unwrap();
+        assert_eq!(exit.action, ValetAction::Exit);
+        assert_eq!(tracker.spots("my_lot").count(), 0);
+    }
+}
@@ -0,0 +1,139 @@
+//! typed channel variables
+//!
+//! freeswitch stores every channel variable as a string. [`FromChannelVar`]
+//! parses them the way freeswitch itself does: booleans through `switch_true`,
+//! lists either `ARRAY::a|:b` encoded, `^^<sep>` delimited or comma separated.
+
+use crate::{
+    de::{parse_bool, split_array},
+    event::EventData,
+};
+
+/// a type that can be parsed from a channel variable value
+pub trait FromChannelVar: Sized {
+    fn from_channel_var(value: &str) -> Option<Self>;
+}
+
+impl FromChannelVar for String {
+    fn from_channel_var(value: &str) -> Option<Self> {
+        Some(value.to_string())
+    }
+}
+
+/// `true`/`yes`/`on`/`1`... and their negatives, anything else is `None`
+impl FromChannelVar for bool {
+    fn from_channel_var(value: &str) -> Option<Self> {
+        parse_bool(value)
+    }
+}
+
+macro_rules! from_str_channel_var {
+    ($($ty:ty),*) => {
+        $(
+            impl FromChannelVar for $ty {
+                fn from_channel_var(value: &str) -> Option<Self> {
+                    value.trim().parse().ok()
+                }
+            }
+        )*
+    };
+}
+
+from_str_channel_var!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+from_str_channel_var!(f32, f64, uuid::Uuid);
+
+/// lists, see [`split_list`]; every item must parse for the list to parse
+impl<T: FromChannelVar> FromChannelVar for Vec<T> {
+    fn from_channel_var(value: &str) -> Option<Self> {
+        split_list(value).map(T::from_channel_var).collect()
+    }
+}
+
+/// split a list valued variable
+///
+/// - `ARRAY::a|:b|:c`, how multi-value variables are flattened
+/// - `^^;a;b;c`, the delimiter is the char after `^^`
+/// - `a,b,c`, plain comma separated
+///
+/// an empty value is an empty list
+pub fn split_list(value: &str) -> Box<dyn Iterator<Item = &str> + '_> {
+    if value.is_empty() {
+        return Box::new(std::iter::empty());
+    }
+    if let Some(items) = split_array(value) {
+        return Box::new(items);
+    }
+    if let Some(rest) = value.strip_prefix("^^") {
+        let mut chars = rest.chars();
+        if let Some(sep) = chars.next() {
+            return Box::new(chars.as_str().split(sep));
+        }
+    }
+    Box::new(value.split(',').map(str::trim))
+}
+
+impl EventData {
+    /// a channel variable parsed as `T`, `None` when missing or unparsable
+    ///
+    /// ```
+    /// # use esl_rs::event::EventData;
+    /// # fn cdr(evt: &EventData) {
+    /// let billsec = evt.get_var_as::<u64>("billsec");
+    /// let codecs = evt.get_var_as::<Vec<String>>("rtp_audio_codecs");
+    /// # }
+    /// ```
+    pub fn get_var_as<T: FromChannelVar>(&self, key: &str) -> Option<T> {
+        T::from_channel_var(&self.get_var(key)?)
+    }
+
+    /// all channel variables, with the `variable_` prefix stripped
+    pub fn vars(&self) -> impl Iterator<Item = (&str, String)> + '_ {
+        self.body.iter().flatten().filter_map(|(k, _)| {
+            let name = k.strip_prefix("variable_")?;
+            Some((name, self.get_body_by_key(k)?))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::json_event;
+
+    #[test]
+    fn typed_vars() {
+        let body = serde_json::json!({
+            "Event-Name": "CHANNEL_ANSWER",
+            "variable_billsec": "42",
+            "variable_answered": "yes",
+            "variable_ratio": "0.5",
+            "variable_codecs": "PCMU, PCMA",
+            "variable_ports": "^^:5060:5080",
+            "variable_array": ["a", "b"],
+            "variable_empty": "",
+        });
+        let data = json_event(body);
+
+        assert_eq!(data.get_var_as::<u64>("billsec"), Some(42));
+        assert_eq!(data.get_var_as::<bool>("answered"), Some(true));
+        assert_eq!(data.get_var_as::<bool>("billsec"), None);
+        assert_eq!(data.get_var_as::<f64>("ratio"), Some(0.5));
+        assert_eq!(
+            data.get_var_as::<Vec<String>>("codecs"),
+            Some(vec!["PCMU".to_string(), "PCMA".to_string()])
+        );
+        assert_eq!(data.get_var_as::<Vec<u16>>("ports"), Some(vec![5060, 5080]));
+        assert_eq!(
+            data.get_var_as::<Vec<String>>("array"),
+            Some(vec!["a".to_string(), "b".to_string()])
+        );
+        assert_eq!(data.get_var_as::<Vec<String>>("empty"), Some(vec![]));
+        assert_eq!(data.get_var_as::<u64>("missing"), None);
+
+        let mut vars: Vec<_> = data.vars().map(|(k, _)| k).collect();
+        vars.sort();
+        assert_eq!(
+            vars,
+            ["answered", "array", "billsec", "codecs", "empty", "ports", "ratio"]
+        );
+    }
+}
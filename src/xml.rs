@@ -0,0 +1,90 @@
+//! just enough xml for the api outputs that only come as xml, such as
+//! `fifo list` and `valet_info`: start tags, their attributes and the text
+//! right after them
+
+use std::collections::HashMap;
+
+/// a start tag
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Element {
+    pub name: String,
+    pub attrs: HashMap<String, String>,
+    /// the text up to the next tag, trimmed
+    pub text: String,
+}
+
+impl Element {
+    pub fn attr(&self, name: &str) -> String {
+        self.attrs.get(name).cloned().unwrap_or_default()
+    }
+
+    pub fn attr_as<T: std::str::FromStr>(&self, name: &str) -> Option<T> {
+        self.attrs.get(name)?.trim().parse().ok()
+    }
+}
+
+/// every start tag in document order, closing tags, comments and
+/// declarations are skipped
+pub(crate) fn elements(xml: &str) -> Vec<Element> {
+    let mut out = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        if rest.starts_with(['/', '?', '!']) {
+            continue;
+        }
+        let Some(end) = rest.find('>') else {
+            break;
+        };
+        let tag = rest[..end].trim_end_matches('/');
+        rest = &rest[end + 1..];
+        let (name, mut attrs) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+        let mut element = Element {
+            name: name.to_string(),
+            text: unescape(rest[..rest.find('<').unwrap_or(rest.len())].trim()),
+            ..Default::default()
+        };
+        while let Some((key, value)) = attrs.split_once('=') {
+            let value = value.trim_start();
+            let Some(quote) = value.chars().next().filter(|c| matches!(c, '"' | '\'')) else {
+                break;
+            };
+            let Some(close) = value[1..].find(quote) else {
+                break;
+            };
+            element
+                .attrs
+                .insert(key.trim().to_string(), unescape(&value[1..close + 1]));
+            attrs = &value[close + 2..];
+        }
+        out.push(element);
+    }
+    out
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scan_elements() {
+        let found = elements(
+            "<?xml version=\"1.0\"?>\n<lots><lot name='a b'>\
+             <extension uuid=\"7f4d\">6001</extension><!-- x --><empty flag=\"&amp;&lt;\"/></lot></lots>",
+        );
+        let names: Vec<_> = found.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["lots", "lot", "extension", "empty"]);
+        assert_eq!(found[1].attr("name"), "a b");
+        assert_eq!(found[2].attr("uuid"), "7f4d");
+        assert_eq!(found[2].text, "6001");
+        assert_eq!(found[3].attr("flag"), "&<");
+    }
+}